@@ -12,13 +12,15 @@ pub fn redis_test(args: TokenStream, input: TokenStream) -> TokenStream {
     let parsed_fn: ItemFn = parse_macro_input!(input);
 
     let mut loaded_module = false;
+    let mut cluster = false;
     for arg in args {
         if let syn::NestedMeta::Meta(syn::Meta::Path(path)) = arg {
             if let Some(ident) = path.get_ident() {
                 match ident.to_string().to_lowercase().as_str() {
                     "loaded_module" => loaded_module = true,
+                    "cluster" => cluster = true,
                     name => {
-                        let msg = format!("Unknown attribute {} is specified; expected `loaded_module` or none", name);
+                        let msg = format!("Unknown attribute {} is specified; expected `loaded_module`, `cluster` or none", name);
                         return syn::Error::new_spanned(path, msg).to_compile_error().into()
                     }
                 }
@@ -59,7 +61,7 @@ pub fn redis_test(args: TokenStream, input: TokenStream) -> TokenStream {
         #[::core::prelude::v1::test]
         #(#attrs)*
         #vis fn #name() #ret {
-            with_redis_conn(stringify!(#name), |#params| {
+            with_redis_conn(stringify!(#name), #cluster, |#params| {
                 #prelude
                 #body
             })
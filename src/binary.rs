@@ -0,0 +1,122 @@
+//! Binary-safe counterparts of the module API wrappers of redis-module, which only deal with UTF-8 `&str`,
+//! along with the few bits of the raw module API redis-module doesn't wrap at all.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::{ptr, slice};
+
+use redis_module::{raw, Context, RedisError, RedisResult, RedisValue, Status};
+
+/// Arguments passed as a vector (`v`) and the writes propagated to replicas and the AOF (`!`), which Redis
+/// doesn't do on its own for the commands a module calls.
+const CALL_FORMAT: &[u8] = b"!v\0";
+
+const REPLY_STRING: c_int = raw::REDISMODULE_REPLY_STRING as c_int;
+const REPLY_ERROR: c_int = raw::REDISMODULE_REPLY_ERROR as c_int;
+const REPLY_INTEGER: c_int = raw::REDISMODULE_REPLY_INTEGER as c_int;
+const REPLY_ARRAY: c_int = raw::REDISMODULE_REPLY_ARRAY as c_int;
+const REPLY_NULL: c_int = raw::REDISMODULE_REPLY_NULL as c_int;
+
+pub type KeyspaceEventCallback = unsafe extern "C" fn(*mut raw::RedisModuleCtx, c_int, *const c_char, *mut raw::RedisModuleString) -> c_int;
+pub type InfoCallback = unsafe extern "C" fn(*mut raw::RedisModuleInfoCtx, c_int);
+
+/// Same as `Context::call`, but passes the arguments as raw bytes and returns string replies
+/// as `RedisValue::StringBuffer`.
+pub fn call(ctx: &Context, cmd: &str, args: &[&[u8]]) -> RedisResult {
+    let cmd = CString::new(cmd).map_err(|_| RedisError::Str("ERR invalid command name"))?;
+    let args = args
+        .iter()
+        .map(|arg| unsafe { raw::RedisModule_CreateString.unwrap()(ctx.ctx, arg.as_ptr() as *const c_char, arg.len() as _) })
+        .collect::<Vec<_>>();
+
+    let reply = unsafe {
+        raw::RedisModule_Call.unwrap()(
+            ctx.ctx,
+            cmd.as_ptr(),
+            CALL_FORMAT.as_ptr() as *const c_char,
+            args.as_ptr(),
+            args.len(),
+        )
+    };
+
+    let result = if reply.is_null() {
+        Err(RedisError::String(format!("ERR failed to call {}", cmd.to_string_lossy())))
+    } else {
+        let parsed = unsafe { parse_reply(reply) };
+        unsafe { raw::RedisModule_FreeCallReply.unwrap()(reply) };
+        parsed
+    };
+
+    for arg in args {
+        unsafe { raw::RedisModule_FreeString.unwrap()(ctx.ctx, arg) };
+    }
+    result
+}
+
+unsafe fn parse_reply(reply: *mut raw::RedisModuleCallReply) -> RedisResult {
+    match raw::RedisModule_CallReplyType.unwrap()(reply) {
+        REPLY_STRING => Ok(RedisValue::StringBuffer(reply_bytes(reply).to_vec())),
+        REPLY_ERROR => Err(RedisError::String(String::from_utf8_lossy(reply_bytes(reply)).into_owned())),
+        REPLY_INTEGER => Ok(RedisValue::Integer(raw::RedisModule_CallReplyInteger.unwrap()(reply))),
+        REPLY_ARRAY => (0..raw::RedisModule_CallReplyLength.unwrap()(reply))
+            .map(|i| parse_reply(raw::RedisModule_CallReplyArrayElement.unwrap()(reply, i)))
+            .collect::<Result<Vec<_>, _>>()
+            .map(RedisValue::Array),
+        REPLY_NULL => Ok(RedisValue::Null),
+        _ => Err(RedisError::Str("ERR unknown reply type")),
+    }
+}
+
+unsafe fn reply_bytes<'a>(reply: *mut raw::RedisModuleCallReply) -> &'a [u8] {
+    let mut len = 0;
+    let ptr = raw::RedisModule_CallReplyStringPtr.unwrap()(reply, &mut len);
+    if ptr.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr as *const u8, len as usize)
+    }
+}
+
+/// Raw bytes of a `RedisModuleString` handed over by Redis, valid for as long as the string itself.
+pub unsafe fn string_bytes<'a>(s: *const raw::RedisModuleString) -> &'a [u8] {
+    let mut len = 0;
+    let ptr = raw::RedisModule_StringPtrLen.unwrap()(s, &mut len);
+    slice::from_raw_parts(ptr as *const u8, len as usize)
+}
+
+pub fn is_replica(ctx: &Context) -> bool {
+    let flags = unsafe { raw::RedisModule_GetContextFlags.unwrap()(ctx.ctx) };
+    flags as u32 & raw::REDISMODULE_CTX_FLAGS_SLAVE as u32 != 0
+}
+
+/// Current unix time in milliseconds, as Redis sees it.
+pub fn milliseconds() -> i64 {
+    unsafe { raw::RedisModule_Milliseconds.unwrap()() }
+}
+
+/// Unlike the `event_handlers` of `redis_module!`, the callback receives the key as a raw `RedisModuleString`.
+pub fn subscribe_to_keyspace_events(ctx: &Context, types: u32, callback: KeyspaceEventCallback) -> Status {
+    to_status(unsafe { raw::RedisModule_SubscribeToKeyspaceEvents.unwrap()(ctx.ctx, types as c_int, Some(callback)) })
+}
+
+fn to_status(status: c_int) -> Status {
+    if status == raw::REDISMODULE_OK as c_int {
+        Status::Ok
+    } else {
+        Status::Err
+    }
+}
+
+pub fn register_info_func(ctx: &Context, callback: InfoCallback) -> Status {
+    to_status(unsafe { raw::RedisModule_RegisterInfoFunc.unwrap()(ctx.ctx, Some(callback)) })
+}
+
+/// Starts the default section of the module's `INFO` output, which is named after the module itself.
+pub unsafe fn info_add_default_section(ctx: *mut raw::RedisModuleInfoCtx) {
+    raw::RedisModule_InfoAddSection.unwrap()(ctx, ptr::null_mut());
+}
+
+pub unsafe fn info_add_field(ctx: *mut raw::RedisModuleInfoCtx, field: &str, value: i64) {
+    let field = CString::new(field).unwrap();
+    raw::RedisModule_InfoAddFieldLongLong.unwrap()(ctx, field.as_ptr() as *mut c_char, value as _);
+}
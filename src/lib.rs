@@ -1,38 +1,127 @@
 #[macro_use]
 extern crate redis_module;
 
-use redis_module::{Context, NotifyEvent, RedisResult, RedisString, Status};
+use std::os::raw::{c_char, c_int};
 
+use redis_module::{raw, Context, LogLevel, RedisResult, RedisString, Status};
+
+mod binary;
+mod metrics;
 mod ops;
 
-fn on_event(ctx: &Context, _event_type: NotifyEvent, _event: &str, key: &str) {
-    if cfg!(debug_assetions) {
-        ctx.log_debug(&format!("Evicting {}", key));
+fn on_event(ctx: &Context, key: &[u8], evicted: bool) {
+    if cfg!(debug_assertions) {
+        ctx.log_debug(&format!("Evicting {}", String::from_utf8_lossy(key)));
+    }
+
+    if let Some(groom) = ops::EventGroom::from(ctx, key, evicted) {
+        groom.perform();
     }
+}
 
-    ops::EventGroom::from(ctx, key).perform();
+unsafe extern "C" fn on_keyspace_event(
+    ctx: *mut raw::RedisModuleCtx,
+    event_type: c_int,
+    _event: *const c_char,
+    key: *mut raw::RedisModuleString,
+) -> c_int {
+    let evicted = event_type == raw::REDISMODULE_NOTIFY_EVICTED as c_int;
+    on_event(&Context::new(ctx), binary::string_bytes(key), evicted);
+    raw::REDISMODULE_OK as c_int
 }
 
-fn init(ctx: &Context, _args: &[RedisString]) -> Status {
-    ops::Init::from(ctx).perform();
+// MODULE LOAD <path> [LAZY_REPAIR]
+fn init(ctx: &Context, args: &[RedisString]) -> Status {
+    for arg in args {
+        match arg.as_slice().to_ascii_uppercase().as_slice() {
+            b"LAZY_REPAIR" => ops::enable_lazy_repair(),
+            _ => {
+                ctx.log(
+                    LogLevel::Warning,
+                    &format!("Unknown module argument [ {} ]", String::from_utf8_lossy(arg.as_slice())),
+                );
+                return Status::Err;
+            }
+        }
+    }
+
+    if let Status::Err = binary::register_info_func(ctx, metrics::info) {
+        return Status::Err;
+    }
 
-    Status::Ok
+    binary::subscribe_to_keyspace_events(
+        ctx,
+        raw::REDISMODULE_NOTIFY_EVICTED | raw::REDISMODULE_NOTIFY_EXPIRED,
+        on_keyspace_event,
+    )
 }
 
-// MAP.MSETEX <namespace> <expiry> <num_indices> <idx_1> <idx_2> ... <idx_n>
+// MAP.MSETEX <namespace> <seconds> | EX <seconds> | PX <milliseconds> | EXAT <unix_time_seconds> | PXAT <unix_time_milliseconds> | KEEPTTL
+//               | PERKEY [EX | PX | EXAT | PXAT] (each line then being <key_i> <value_i> <expiry_i> <idx_1_for_k_i> ...)
+//               [NX | XX | CONDITIONAL] (each line then being <key_i> <value_i> [<expiry_i>] NX|XX|ANY <idx_1_for_k_i> ...)
+//               [CAS] (each line then being <key_i> <value_i> [<expiry_i>] <expected_version_i> <idx_1_for_k_i> ...)
+//               [NUMERIC <idx> | LEX <idx> | GEO <idx> ...] (indices backed by a sorted set, queried by MAP.RANGE_BY_INDEX
+//               resp. MAP.GEO_BY_INDEX, geo index values being <longitude>,<latitude>)
+//               <num_indices> <idx_1> <idx_2> ... <idx_n>
 //               <key_1> <value_1> <idx_1_for_k_1> <idx_2_for_k_1> ... <idx_n_for_k_1>
 //               <key_2> <value_2> <idx_1_for_k_2> <idx_2_for_k_2> ... <idx_n_for_k_2>
 //               ...
 //               <key_n> <value_n> <idx_1_for_k_n> <idx_2_for_k_n> ... <idx_n_for_k_n>
+//
+//    or with index name/value pairs given on each line (leaving out the indices a key has no value of)
+//               ... PAIRS <key_1> <value_1> <num_pairs_1> <idx_1> <idx_1_val> ... <idx_n> <idx_n_val>
+//               ...
 fn msetex_indexed(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    ops::Set::from(ctx, args)?.process()
+    ops::Set::from_msetex(ctx, args)?.process()
 }
 
-// MAP.get_by_index <namespace> idx idx_val
+// MAP.MSET_INDEXED <namespace> [NX | XX | CONDITIONAL] [CAS] [NUMERIC <idx> | LEX <idx> | GEO <idx> ...] <num_indices> <idx_1> <idx_2> ... <idx_n>
+//               <key_1> <value_1> <idx_1_for_k_1> <idx_2_for_k_1> ... <idx_n_for_k_1>
+//               ...
+//               <key_n> <value_n> <idx_1_for_k_n> <idx_2_for_k_n> ... <idx_n_for_k_n>
+//
+//    or with index name/value pairs given on each line the same as MAP.MSETEX
+fn mset_indexed(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ops::Set::from_mset(ctx, args)?.process()
+}
+
+// MAP.get_by_index <namespace> idx idx_val [CURSOR cursor] [COUNT count] [KEYSONLY | WITHKEYS] [WITHTTL] [WITHINDICES] [WITHVERSION]
 fn get_by_index(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     ops::Get::from(ctx, args)?.process()
 }
 
+// MAP.range_by_index <namespace> idx min max [BYLEX] | PREFIX prefix [REV] [LIMIT offset count] [KEYSONLY | WITHKEYS] [WITHTTL] [WITHINDICES] [WITHVERSION]
+fn range_by_index(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ops::Range::from(ctx, args)?.process()
+}
+
+// MAP.geo_by_index <namespace> idx longitude latitude BYRADIUS radius M|KM|FT|MI | BYBOX width height M|KM|FT|MI
+//              [ASC | DESC] [COUNT count] [KEYSONLY | WITHKEYS] [WITHTTL] [WITHINDICES] [WITHVERSION]
+fn geo_by_index(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ops::GeoSearch::from(ctx, args)?.process()
+}
+
+// MAP.get <namespace> key [WITHTTL] [WITHINDICES] [WITHVERSION]
+fn get(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ops::MultiGet::from_get(ctx, args)?.process()
+}
+
+// MAP.mget <namespace> <num_keys> k1 k2 ... kn [WITHTTL] [WITHINDICES] [WITHVERSION]
+fn mget(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ops::MultiGet::from_mget(ctx, args)?.process()
+}
+
+// MAP.query <namespace> AND|OR <num_predicates> idx_1 idx_1_val ... idx_n idx_n_val
+//              [NOT <num_predicates> idx_1 idx_1_val ... idx_n idx_n_val] [KEYSONLY | WITHKEYS] [WITHTTL] [WITHINDICES] [WITHVERSION]
+fn query(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ops::Query::from(ctx, args)?.process()
+}
+
+// MAP.update_indices <namespace> key [VERSION expected_version] SET idx idx_val | DEL idx [SET idx idx_val | DEL idx ...]
+fn update_indices(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ops::UpdateIndices::from(ctx, args)?.process()
+}
+
 // MAP.rem_by_index <namespace> idx idx_val
 fn rem_by_index(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     ops::RemoveByIndex::from(ctx, args)?.process()
@@ -43,18 +132,42 @@ fn mrem(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     ops::Remove::from(ctx, args)?.process()
 }
 
+// MAP.info <namespace>
+fn info(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ops::Stats::from(ctx, args)?.process()
+}
+
+// MAP.fsck [NAMESPACE <namespace>] [REPAIR]
+fn fsck(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ops::Fsck::from(ctx, args)?.process()
+}
+
+// MAP.migrate_layout <namespace>
+fn migrate_layout(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ops::Migrate::from(ctx, args)?.process()
+}
+
 redis_module! {
-    name: "map",
+    name: "dragon_map",
     version: 1,
     data_types: [],
     init: init,
     commands: [
-        ["map.msetex_indexed", msetex_indexed, "write deny-oom no-cluster", 1, 1, 1],
-        ["map.mrem", mrem, "write no-cluster", 1, 1, 1],
-        ["map.get_by_index", get_by_index, "readonly no-cluster", 1, 1, 1],
-        ["map.rem_by_index", rem_by_index, "write no-cluster", 1, 1, 1],
+        ["map.msetex_indexed", msetex_indexed, "write deny-oom", 1, 1, 1],
+        ["map.mset_indexed", mset_indexed, "write deny-oom", 1, 1, 1],
+        ["map.mrem", mrem, "write", 1, 1, 1],
+        ["map.get", get, "readonly", 1, 1, 1],
+        ["map.mget", mget, "readonly", 1, 1, 1],
+        // lookups stay readonly to be served by replicas too, although on primaries given LAZY_REPAIR they
+        // remove what's left of entries whose data key is already gone (their meta and index members)
+        ["map.get_by_index", get_by_index, "readonly", 1, 1, 1],
+        ["map.range_by_index", range_by_index, "readonly", 1, 1, 1],
+        ["map.geo_by_index", geo_by_index, "readonly", 1, 1, 1],
+        ["map.query", query, "readonly", 1, 1, 1],
+        ["map.update_indices", update_indices, "write deny-oom", 1, 1, 1],
+        ["map.rem_by_index", rem_by_index, "write", 1, 1, 1],
+        ["map.info", info, "readonly", 1, 1, 1],
+        ["map.migrate_layout", migrate_layout, "write", 1, 1, 1],
+        ["map.fsck", fsck, "write", 0, 0, 0],
     ],
-    event_handlers: [
-        [@EVICTED @EXPIRED: on_event]
-    ]
 }
@@ -0,0 +1,119 @@
+//! Counters of the module's activity, held in module memory (hence reset on every load of the module)
+//! and reported by `INFO dragon_map`, or per namespace by `MAP.INFO <namespace>`.
+
+use std::collections::BTreeMap;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+use redis_module::raw;
+
+use crate::binary;
+
+#[derive(Clone, Copy)]
+pub enum Metric {
+    /// Keyspace events of the module's keys groomed.
+    Events,
+    KeysExpired,
+    KeysEvicted,
+    /// Keys cleaned up without meta to tell which index sets they were members of.
+    MetaMissing,
+    /// Index members found without their data key on lookups.
+    StaleMembersRepaired,
+    /// Entries written.
+    Sets,
+    /// Entries read by their keys.
+    Gets,
+    /// Entries removed explicitly.
+    Removes,
+    /// Index lookups finding at least one entry.
+    IndexHits,
+    /// Index lookups finding no entry at all.
+    IndexMisses,
+}
+
+const METRICS: [Metric; 10] = [
+    Metric::Events,
+    Metric::KeysExpired,
+    Metric::KeysEvicted,
+    Metric::MetaMissing,
+    Metric::StaleMembersRepaired,
+    Metric::Sets,
+    Metric::Gets,
+    Metric::Removes,
+    Metric::IndexHits,
+    Metric::IndexMisses,
+];
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicI64 = AtomicI64::new(0);
+
+static COUNTERS: [AtomicI64; METRICS.len()] = [ZERO; METRICS.len()];
+
+impl Metric {
+    fn name(self) -> &'static str {
+        match self {
+            Metric::Events => "events",
+            Metric::KeysExpired => "keys_expired",
+            Metric::KeysEvicted => "keys_evicted",
+            Metric::MetaMissing => "meta_missing",
+            Metric::StaleMembersRepaired => "stale_members_repaired",
+            Metric::Sets => "sets",
+            Metric::Gets => "gets",
+            Metric::Removes => "removes",
+            Metric::IndexHits => "index_hits",
+            Metric::IndexMisses => "index_misses",
+        }
+    }
+
+    pub fn incr(self) {
+        self.add(1);
+    }
+
+    pub fn add(self, n: i64) {
+        COUNTERS[self as usize].fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn get(self) -> i64 {
+        COUNTERS[self as usize].load(Ordering::Relaxed)
+    }
+}
+
+/// Counters of a single namespace.
+#[derive(Default, Clone, Copy)]
+pub struct NamespaceStats {
+    pub expirations: i64,
+    pub evictions: i64,
+    pub removals: i64,
+    /// Reads finding the entries (or any entry when looking them up by index).
+    pub hits: i64,
+    pub misses: i64,
+}
+
+/// Namespaces are tracked from their first recorded activity, which includes reads of namespaces holding
+/// no data at all, so the number of them is capped to keep arbitrary namespace arguments from growing the map.
+const MAX_NAMESPACES: usize = 10_000;
+
+static NAMESPACE_STATS: Mutex<BTreeMap<Vec<u8>, NamespaceStats>> = Mutex::new(BTreeMap::new());
+
+/// Activity of namespaces beyond the first `MAX_NAMESPACES` ones is not recorded.
+pub fn record(namespace: &[u8], update: impl FnOnce(&mut NamespaceStats)) {
+    let mut stats = NAMESPACE_STATS.lock().unwrap();
+    match stats.get_mut(namespace) {
+        Some(namespace_stats) => update(namespace_stats),
+        None if stats.len() < MAX_NAMESPACES => update(stats.entry(namespace.to_vec()).or_default()),
+        None => {}
+    }
+}
+
+pub fn namespace_stats(namespace: &[u8]) -> NamespaceStats {
+    NAMESPACE_STATS.lock().unwrap().get(namespace).copied().unwrap_or_default()
+}
+
+/// Info callback of the module, the counters make up its default section named after the module.
+pub unsafe extern "C" fn info(ctx: *mut raw::RedisModuleInfoCtx, _for_crash_report: c_int) {
+    binary::info_add_default_section(ctx);
+    for metric in &METRICS {
+        binary::info_add_field(ctx, metric.name(), metric.get());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashSet;
+
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use super::*;
+
+/// Verifies the invariants of the key layout for one or all namespaces (optionally fixing the violations):
+///
+/// * every meta hash has its data key
+/// * every index value listed in a meta hash has the key as a member of the index set (or sorted index)
+/// * every index set (or sorted index) member has its data key and a meta hash listing the index value
+/// * every data key has a meta hash (which does not hold for entries written without any index
+///   by the module before it kept versions, nor for keys of other applications shaped like data keys,
+///   e.g. `{user1}:profile`, which are counted all the same and never repaired)
+/// * every recorded index kind belongs to an index with keys left (kinds outlive the entries, so a repair
+///   drops those of unused indices, which can then be written as another kind)
+pub struct Fsck<'a> {
+    ctx: &'a Context,
+    namespace: Option<RedisString>,
+    repair: bool,
+}
+
+#[derive(Default)]
+struct Report {
+    orphaned_meta: i64,
+    missing_index_members: i64,
+    dangling_index_members: i64,
+    data_without_meta: i64,
+    unused_index_kinds: i64,
+}
+
+impl<'a> Fsck<'a> {
+    pub fn from(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        let mut args = args.into_iter().skip(1);
+
+        let mut namespace = None;
+        let mut repair = false;
+        while let Some(arg) = args.next() {
+            match arg.as_slice().to_ascii_uppercase().as_slice() {
+                b"NAMESPACE" => {
+                    let ns = args.next_arg()?;
+                    validate_namespace(ns.as_slice())?;
+                    namespace = Some(ns);
+                }
+                b"REPAIR" => repair = true,
+                _ => return Err(RedisError::Str("ERR syntax error")),
+            }
+        }
+
+        Ok(Fsck { ctx, namespace, repair })
+    }
+
+    pub fn process(&self) -> RedisResult {
+        let tag_pattern = match &self.namespace {
+            Some(namespace) => glob_escape(&self.scoped(namespace.as_slice()).hash_tag()),
+            None => [TAG_OPEN, b'*', TAG_CLOSE].to_vec(),
+        };
+        let mut report = Report::default();
+
+        // orphaned meta goes first, as cleaning it also takes care of the index members it lists
+        for meta_key in self.scan_all(&[META_PREFIX, &tag_pattern, b":*"].concat())? {
+            if let Some(ModuleKey::Meta { namespace, key }) = parse_key(&meta_key) {
+                self.check_meta(&self.scoped(namespace), key, &mut report)?;
+            }
+        }
+
+        // namespace, kind and name of the indices with keys left after the checks
+        let mut used_indices = HashSet::new();
+        for idx_key in self.scan_all(&[INDEX_PREFIX, &tag_pattern, b":*"].concat())? {
+            if let Some(ModuleKey::Index { namespace, idx, idx_val }) = parse_key(&idx_key) {
+                self.check_index(&self.scoped(namespace), &idx_key, &idx, idx_val, &mut report)?;
+                if self.exists(&idx_key)? {
+                    used_indices.insert((namespace.to_vec(), IndexKind::Exact.name().to_vec(), idx.into_owned()));
+                }
+            }
+        }
+
+        for kind in SortedIndex::ALL {
+            for idx_key in self.scan_all(&[kind.key_prefix(), &tag_pattern, b":*"].concat())? {
+                if let Some(ModuleKey::SortedIndex { kind, namespace, idx }) = parse_key(&idx_key) {
+                    self.check_sorted_index(&self.scoped(namespace), &idx_key, kind, idx, &mut report)?;
+                    if self.exists(&idx_key)? {
+                        used_indices.insert((namespace.to_vec(), IndexKind::Sorted(kind).name().to_vec(), idx.to_vec()));
+                    }
+                }
+            }
+        }
+
+        for kinds_key in self.scan_all(&[INDEX_KINDS_PREFIX, &tag_pattern].concat())? {
+            if let Some(ModuleKey::IndexKinds { namespace }) = parse_key(&kinds_key) {
+                for pair in self.hgetall(&kinds_key)?.chunks_exact(2) {
+                    let (idx, kind) = (&pair[0], &pair[1]);
+                    if !used_indices.contains(&(namespace.to_vec(), kind.clone(), idx.clone())) {
+                        report.unused_index_kinds += 1;
+                        if self.repair {
+                            self.call::<()>("HDEL", &[&kinds_key, idx])?;
+                        }
+                    }
+                }
+            }
+        }
+
+        // any key starting with a hash tag parses as a data key, so foreign ones such as `{user1}:profile` are counted too
+        for data_key in self.scan_all(&[tag_pattern.as_slice(), b":*"].concat())? {
+            if let Some(ModuleKey::Data { namespace, key }) = parse_key(&data_key) {
+                if !self.exists(&self.scoped(namespace).prefixed_meta(key))? {
+                    report.data_without_meta += 1;
+                }
+            }
+        }
+
+        Ok(RedisValue::Array(vec![
+            RedisValue::BulkString("orphaned_meta".to_string()),
+            RedisValue::Integer(report.orphaned_meta),
+            RedisValue::BulkString("missing_index_members".to_string()),
+            RedisValue::Integer(report.missing_index_members),
+            RedisValue::BulkString("dangling_index_members".to_string()),
+            RedisValue::Integer(report.dangling_index_members),
+            RedisValue::BulkString("data_without_meta".to_string()),
+            RedisValue::Integer(report.data_without_meta),
+            RedisValue::BulkString("unused_index_kinds".to_string()),
+            RedisValue::Integer(report.unused_index_kinds),
+        ]))
+    }
+
+    fn check_meta(&self, scope: &Scope, key: &[u8], report: &mut Report) -> Result<(), RedisError> {
+        if !self.exists(&scope.prefixed(key))? {
+            report.orphaned_meta += 1;
+            if self.repair {
+                scope.clean_key(key)?;
+            }
+            return Ok(());
+        }
+
+        let meta = self.hgetall(&scope.prefixed_meta(key))?;
+        for (idx, idx_val) in index_pairs(&meta) {
+            let idx_key = scope.prefixed_idx(&idx, idx_val);
+            if !self.sismember(&idx_key, key)? {
+                report.missing_index_members += 1;
+                if self.repair {
+                    self.call::<()>("SADD", &[&idx_key, key])?;
+                }
+            }
+        }
+        for (kind, idx, idx_val) in sorted_index_pairs(&meta) {
+            let idx_key = scope.prefixed_sorted_idx(kind, idx);
+            let score: Option<Vec<u8>> = self.call("ZSCORE", &[&idx_key, &kind.member(key, idx_val)])?;
+            if score.is_none() {
+                report.missing_index_members += 1;
+                if self.repair {
+                    scope.add_to_sorted_index(key, kind, idx, idx_val)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_index(&self, scope: &Scope, idx_key: &[u8], idx: &[u8], idx_val: &[u8], report: &mut Report) -> Result<(), RedisError> {
+        for member in self.smembers(idx_key)? {
+            let consistent = self.exists(&scope.prefixed(&member))? && self.meta_lists(&scope.prefixed_meta(&member), idx, idx_val)?;
+            if !consistent {
+                report.dangling_index_members += 1;
+                if self.repair {
+                    self.srem(idx_key, &member)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Lex members carry the index value along with the key, which the meta must record as is,
+    /// other members only need the meta to record a value of the index.
+    fn check_sorted_index(
+        &self,
+        scope: &Scope,
+        idx_key: &[u8],
+        kind: SortedIndex,
+        idx: &[u8],
+        report: &mut Report,
+    ) -> Result<(), RedisError> {
+        let members: Vec<Vec<u8>> = self.call("ZRANGE", &[idx_key, b"0", b"-1"])?;
+        for member in members {
+            let listed = match kind {
+                SortedIndex::Lex => split_lex_member(&member).map(|(idx_val, key)| (key, Some(idx_val))),
+                SortedIndex::Numeric | SortedIndex::Geo => Some((member.as_slice(), None)),
+            };
+            let consistent = match listed {
+                Some((key, idx_val)) => {
+                    let recorded = self.hget(&scope.prefixed_meta(key), &kind.field(idx))?;
+                    self.exists(&scope.prefixed(key))? && recorded.is_some() && (idx_val.is_none() || recorded.as_deref() == idx_val)
+                }
+                None => false,
+            };
+            if !consistent {
+                report.dangling_index_members += 1;
+                if self.repair {
+                    self.call::<()>("ZREM", &[idx_key, &member])?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the meta records `idx_val` as the (single or one of the several) value(s) of `idx`.
+    fn meta_lists(&self, meta_key: &[u8], idx: &[u8], idx_val: &[u8]) -> Result<bool, RedisError> {
+        Ok(self.hget(meta_key, idx)?.as_deref() == Some(idx_val) || self.hget(meta_key, &tag_field(idx, idx_val))?.is_some())
+    }
+
+    fn scoped<'n>(&self, namespace: &'n [u8]) -> Scope<'n>
+    where
+        'a: 'n,
+    {
+        Scope { ctx: self.ctx, namespace }
+    }
+}
+
+impl Contextual for Fsck<'_> {
+    fn context(&self) -> &Context {
+        self.ctx
+    }
+}
+
+/// Namespace the checked keys belong to, which is only known once they have been parsed.
+struct Scope<'a> {
+    ctx: &'a Context,
+    namespace: &'a [u8],
+}
+
+impl Namespaced for Scope<'_> {
+    fn namespace(&self) -> &[u8] {
+        self.namespace
+    }
+}
+
+impl Contextual for Scope<'_> {
+    fn context(&self) -> &Context {
+        self.ctx
+    }
+}
+
+impl CleanOperation for Scope<'_> {}
@@ -0,0 +1,101 @@
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString};
+
+use super::*;
+
+/// Looks up entries positioned by a geo index within a radius or a box around a point, backed by `GEOSEARCH`.
+pub struct GeoSearch<'a> {
+    ctx: &'a Context,
+    namespace: RedisString,
+    idx: RedisString,
+    longitude: RedisString,
+    latitude: RedisString,
+    /// `BYRADIUS <radius> <unit>` or `BYBOX <width> <height> <unit>`, passed on as is.
+    shape: Vec<RedisString>,
+    order: Option<RedisString>,
+    count: Option<u64>,
+    options: ReplyOptions,
+}
+
+impl<'a> GeoSearch<'a> {
+    pub fn from(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        let mut args = args.into_iter().skip(1);
+
+        let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
+        let idx = args.next_arg()?;
+        let longitude = args.next_arg()?;
+        let latitude = args.next_arg()?;
+
+        let shape_keyword = args.next_arg()?;
+        let shape_args = match shape_keyword.as_slice().to_ascii_uppercase().as_slice() {
+            b"BYRADIUS" => 2,
+            b"BYBOX" => 3,
+            _ => return Err(RedisError::Str("ERR shape must be one of BYRADIUS, BYBOX!")),
+        };
+        let mut shape = vec![shape_keyword];
+        for _ in 0..shape_args {
+            shape.push(args.next_arg()?);
+        }
+
+        let mut order = None;
+        let mut count = None;
+        let mut flags = vec![];
+        while let Some(arg) = args.next() {
+            match arg.as_slice().to_ascii_uppercase().as_slice() {
+                b"ASC" | b"DESC" => order = Some(arg),
+                b"COUNT" => count = Some(args.next_u64()?),
+                _ => flags.push(arg),
+            }
+        }
+        let options = ReplyOptions::parse_for_lookup(flags.into_iter())?;
+
+        Ok(GeoSearch {
+            ctx,
+            namespace,
+            idx,
+            longitude,
+            latitude,
+            shape,
+            order,
+            count,
+            options,
+        })
+    }
+
+    /// Unless ordered by the distance from the point (`ASC` or `DESC`), the entries come in no particular order.
+    pub fn process(&self) -> RedisResult {
+        let idx_key = self.prefixed_sorted_idx(SortedIndex::Geo, self.idx.as_slice());
+        let count = self.count.map(|count| count.to_string());
+
+        let mut args = vec![
+            idx_key.as_slice(),
+            &b"FROMLONLAT"[..],
+            self.longitude.as_slice(),
+            self.latitude.as_slice(),
+        ];
+        args.extend(self.shape.iter().map(RedisString::as_slice));
+        args.extend(self.order.as_ref().map(RedisString::as_slice));
+        if let Some(count) = &count {
+            args.extend([&b"COUNT"[..], count.as_bytes()]);
+        }
+        let keys: Vec<Vec<u8>> = self.call("GEOSEARCH", &args)?;
+
+        self.entries_reply(&keys, &[idx_key], &self.options)
+    }
+}
+
+impl Namespaced for GeoSearch<'_> {
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
+    }
+}
+
+impl Contextual for GeoSearch<'_> {
+    fn context(&self) -> &Context {
+        self.ctx
+    }
+}
+
+impl CleanOperation for GeoSearch<'_> {}
+
+impl EntryReply for GeoSearch<'_> {}
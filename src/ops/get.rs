@@ -1,15 +1,22 @@
-use std::borrow::Borrow;
-
-use itertools::Itertools;
 use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
 
 use super::*;
 
+const DEFAULT_PAGE_SIZE: u64 = 10;
+
 pub struct Get<'a> {
     ctx: &'a Context,
     namespace: RedisString,
     idx: RedisString,
     idx_val: RedisString,
+    page: Option<Page>,
+    options: ReplyOptions,
+}
+
+/// Position within the index set for paging through it by `SSCAN`.
+struct Page {
+    cursor: Vec<u8>,
+    count: u64,
 }
 
 impl<'a> Get<'a> {
@@ -17,33 +24,61 @@ impl<'a> Get<'a> {
         let mut args = args.into_iter().skip(1);
 
         let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
         let idx = args.next_arg()?;
         let idx_val = args.next_arg()?;
 
+        let mut page = None;
+        let mut flags = vec![];
+        while let Some(arg) = args.next() {
+            let new_page = || Page {
+                cursor: b"0".to_vec(),
+                count: DEFAULT_PAGE_SIZE,
+            };
+            match arg.as_slice().to_ascii_uppercase().as_slice() {
+                b"CURSOR" => page.get_or_insert_with(new_page).cursor = args.next_arg()?.as_slice().to_vec(),
+                b"COUNT" => {
+                    // rejected up front rather than by SSCAN with a bare syntax error
+                    let count = args.next_i64()?;
+                    if count < 1 {
+                        return Err(RedisError::Str("ERR COUNT must be positive!"));
+                    }
+                    page.get_or_insert_with(new_page).count = count as u64;
+                }
+                _ => flags.push(arg),
+            }
+        }
+        let options = ReplyOptions::parse_for_lookup(flags.into_iter())?;
+
         Ok(Get {
             ctx,
             namespace,
             idx,
             idx_val,
+            page,
+            options,
         })
     }
 
     pub fn process(&self) -> RedisResult {
-        let idx_key = &self.prefixed_idx(self.idx.borrow(), self.idx_val.borrow());
-        let keys = self.smembers(idx_key)?;
-        if keys.is_empty() {
-            Ok(RedisValue::Array(vec![]))
-        } else {
-            let prefixed_keys = keys.iter().map(|k| self.prefixed(k)).collect_vec();
-            let prefixed_keys_str = prefixed_keys.iter().map(AsRef::as_ref).collect_vec();
-            self.ctx.call("MGET", prefixed_keys_str.as_slice())
+        let idx_key = self.prefixed_idx(self.idx.as_slice(), self.idx_val.as_slice());
+        match &self.page {
+            None => {
+                let keys = self.smembers(&idx_key)?;
+                self.entries_reply(&keys, &[idx_key], &self.options)
+            }
+            Some(page) => {
+                let (cursor, keys) = self.sscan(&idx_key, &page.cursor, page.count)?;
+                let entries = self.entries_reply(&keys, &[idx_key], &self.options)?;
+                Ok(RedisValue::Array(vec![RedisValue::StringBuffer(cursor), entries]))
+            }
         }
     }
 }
 
 impl Namespaced for Get<'_> {
-    fn namespace(&self) -> &str {
-        self.namespace.borrow()
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
     }
 }
 
@@ -52,3 +87,7 @@ impl Contextual for Get<'_> {
         self.ctx
     }
 }
+
+impl CleanOperation for Get<'_> {}
+
+impl EntryReply for Get<'_> {}
@@ -4,31 +4,65 @@ use super::*;
 
 pub struct EventGroom<'a> {
     ctx: &'a Context,
-    namespace: &'a str,
-    key: &'a str,
+    namespace: &'a [u8],
+    key: &'a [u8],
+    meta_event: bool,
+    evicted: bool,
 }
 
 impl<'a> EventGroom<'a> {
-    pub fn from(ctx: &'a Context, key: &'a str) -> Self {
-        let (namespace, key) = split_namespace(key);
-        Self { ctx, namespace, key }
+    /// Returns `None` for keys that are not part of the module's layout (or need no grooming),
+    /// as the keyspace events are delivered for every key of the instance.
+    pub fn from(ctx: &'a Context, key: &'a [u8], evicted: bool) -> Option<Self> {
+        let (namespace, key, meta_event) = match parse_key(key)? {
+            ModuleKey::Data { namespace, key } => (namespace, key, false),
+            ModuleKey::Meta { namespace, key } => (namespace, key, true),
+            // index sets get groomed along with the keys they contain
+            ModuleKey::Index { .. } | ModuleKey::SortedIndex { .. } => return None,
+            ModuleKey::IndexKinds { .. } => return None,
+        };
+        Some(Self {
+            ctx,
+            namespace,
+            key,
+            meta_event,
+            evicted,
+        })
     }
 
     pub fn perform(&self) {
-        self.incr("events").unwrap();
-        self.incr("keys_expired").unwrap();
+        self.groom().unwrap_or_else(|e| {
+            self.ctx.log(
+                LogLevel::Warning,
+                &format!("Error grooming key [ {} ]: {}", String::from_utf8_lossy(self.key), e),
+            )
+        });
+    }
+
+    fn groom(&self) -> Result<(), RedisError> {
+        // a data key without meta was either written without indices by an older version of the module
+        // or does not belong to it at all, in both cases there is nothing to groom
+        if !self.meta_event && !self.exists(&self.prefixed_meta(self.key))? {
+            return Ok(());
+        }
+
+        Metric::Events.incr();
+        if self.evicted {
+            Metric::KeysEvicted.incr();
+            metrics::record(self.namespace(), |stats| stats.evictions += 1);
+        } else {
+            Metric::KeysExpired.incr();
+            metrics::record(self.namespace(), |stats| stats.expirations += 1);
+        }
 
         // clean_key ensures that meta is removed in case of key expiry
         // and vice versa that key is removed in case of meta expiry
-        self.clean_key(self.key).unwrap_or_else(|e| {
-            self.ctx
-                .log(LogLevel::Warning, &format!("Error grooming key [ {} ]: {}", self.key, e))
-        });
+        self.clean_key(self.key)
     }
 }
 
 impl Namespaced for EventGroom<'_> {
-    fn namespace(&self) -> &str {
+    fn namespace(&self) -> &[u8] {
         self.namespace
     }
 }
@@ -0,0 +1,401 @@
+use std::borrow::Cow;
+
+use redis_module::RedisError;
+
+pub(super) const SEPARATOR: u8 = b':';
+pub(super) const ESCAPE: u8 = b'\\';
+pub(super) const TAG_OPEN: u8 = b'{';
+pub(super) const TAG_CLOSE: u8 = b'}';
+pub(super) const META_PREFIX: &[u8] = b"meta_";
+pub(super) const INDEX_PREFIX: &[u8] = b"idx_";
+pub(super) const NUMERIC_INDEX_PREFIX: &[u8] = b"numidx_";
+pub(super) const LEX_INDEX_PREFIX: &[u8] = b"lexidx_";
+pub(super) const GEO_INDEX_PREFIX: &[u8] = b"geoidx_";
+pub(super) const VERSION_COUNTER_PREFIX: &[u8] = b"version_";
+pub(super) const INDEX_KINDS_PREFIX: &[u8] = b"indices_";
+/// Meta fields of the module's own are told apart from index names by this prefix.
+pub(super) const RESERVED_PREFIX: &[u8] = b"__";
+pub(super) const VERSION_FIELD: &[u8] = b"__version";
+/// Prefix of the meta fields of indices with several values, see `tag_field`.
+const TAG_FIELD_PREFIX: &[u8] = b"__tag:";
+/// Prefix of the meta fields of numeric indices, see `SortedIndex::field`.
+const NUMERIC_FIELD_PREFIX: &[u8] = b"__num:";
+const LEX_FIELD_PREFIX: &[u8] = b"__lex:";
+const GEO_FIELD_PREFIX: &[u8] = b"__geo:";
+/// Separates the longitude and latitude making up the value of a geo index, e.g. `16.6068,49.1951`.
+const COORDINATES_SEPARATOR: u8 = b',';
+pub(super) const GEO_VALUE_ERROR: &str = "ERR geo index value must be <longitude>,<latitude>!";
+/// Latitudes beyond are not representable by the geohash Redis keeps the points as.
+const MAX_LATITUDE: f64 = 85.05112878;
+/// Ends the index value within the members of a lex index, hence not allowed in the values themselves.
+pub(super) const LEX_TERMINATOR: u8 = b'\0';
+
+/// Builds the Redis keys of a namespace.
+///
+/// The namespace is enclosed in `{}` to make it the cluster hash tag of all its keys. It is kept verbatim
+/// (it can't contain braces) so that the keys hash to the same slot as the namespace argument clients route by.
+/// Index names get `:` and `\` escaped by a `\`, so that e.g. index `b:c` with value `d` and index `b`
+/// with value `c:d` don't share a key.
+pub(super) trait Namespaced {
+    fn namespace(&self) -> &[u8];
+
+    fn hash_tag(&self) -> Vec<u8> {
+        [&[TAG_OPEN][..], self.namespace(), &[TAG_CLOSE]].concat()
+    }
+
+    fn prefixed(&self, key: &[u8]) -> Vec<u8> {
+        [self.hash_tag().as_slice(), &[SEPARATOR], key].concat()
+    }
+
+    fn prefixed_meta(&self, key: &[u8]) -> Vec<u8> {
+        [META_PREFIX, self.hash_tag().as_slice(), &[SEPARATOR], key].concat()
+    }
+
+    fn prefixed_idx(&self, idx: &[u8], idx_val: &[u8]) -> Vec<u8> {
+        [
+            INDEX_PREFIX,
+            self.hash_tag().as_slice(),
+            &[SEPARATOR],
+            escape(idx).as_ref(),
+            &[SEPARATOR],
+            idx_val,
+        ]
+        .concat()
+    }
+
+    /// Counter the versions of all the entries of the namespace are drawn from, outliving the entries.
+    fn prefixed_version_counter(&self) -> Vec<u8> {
+        [VERSION_COUNTER_PREFIX, self.hash_tag().as_slice()].concat()
+    }
+
+    /// Hash of index name -> `IndexKind` of all the indices ever written within the namespace.
+    fn prefixed_index_kinds(&self) -> Vec<u8> {
+        [INDEX_KINDS_PREFIX, self.hash_tag().as_slice()].concat()
+    }
+
+    /// A sorted index keeps all the keys of the index in a single sorted set.
+    fn prefixed_sorted_idx(&self, kind: SortedIndex, idx: &[u8]) -> Vec<u8> {
+        [kind.key_prefix(), self.hash_tag().as_slice(), &[SEPARATOR], idx].concat()
+    }
+}
+
+/// Kinds of indices backed by a sorted set per index name (rather than a set per index value),
+/// which allows for range queries. An entry has at most a single value of such an index.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum SortedIndex {
+    /// Keys scored by the index value, which must be a number.
+    Numeric,
+    /// Index values ordered lexicographically, each followed by the key (see `member`) and all scored `0`.
+    Lex,
+    /// Keys positioned by the index value, `<longitude>,<latitude>`.
+    Geo,
+}
+
+impl SortedIndex {
+    pub(super) const ALL: [SortedIndex; 3] = [SortedIndex::Numeric, SortedIndex::Lex, SortedIndex::Geo];
+
+    pub(super) fn parse(keyword: &[u8]) -> Option<Self> {
+        match keyword.to_ascii_uppercase().as_slice() {
+            b"NUMERIC" => Some(SortedIndex::Numeric),
+            b"LEX" => Some(SortedIndex::Lex),
+            b"GEO" => Some(SortedIndex::Geo),
+            _ => None,
+        }
+    }
+
+    pub(super) fn key_prefix(self) -> &'static [u8] {
+        match self {
+            SortedIndex::Numeric => NUMERIC_INDEX_PREFIX,
+            SortedIndex::Lex => LEX_INDEX_PREFIX,
+            SortedIndex::Geo => GEO_INDEX_PREFIX,
+        }
+    }
+
+    /// Keyword declaring the kind, as parsed by `parse`.
+    fn keyword(self) -> &'static [u8] {
+        match self {
+            SortedIndex::Numeric => b"NUMERIC",
+            SortedIndex::Lex => b"LEX",
+            SortedIndex::Geo => b"GEO",
+        }
+    }
+
+    fn field_prefix(self) -> &'static [u8] {
+        match self {
+            SortedIndex::Numeric => NUMERIC_FIELD_PREFIX,
+            SortedIndex::Lex => LEX_FIELD_PREFIX,
+            SortedIndex::Geo => GEO_FIELD_PREFIX,
+        }
+    }
+
+    /// Member of the sorted set standing for the key, which for lex indices is `<idx_val>\0<key>`,
+    /// so that the members are ordered by the value.
+    pub(super) fn member<'k>(self, key: &'k [u8], idx_val: &[u8]) -> Cow<'k, [u8]> {
+        match self {
+            SortedIndex::Numeric | SortedIndex::Geo => Cow::Borrowed(key),
+            SortedIndex::Lex => Cow::Owned([idx_val, &[LEX_TERMINATOR], key].concat()),
+        }
+    }
+
+    /// Meta field recording the value of the index, e.g. `__num:price`.
+    pub(super) fn field(self, idx: &[u8]) -> Vec<u8> {
+        [self.field_prefix(), idx].concat()
+    }
+
+    pub(super) fn validate_value(self, idx_val: &[u8]) -> Result<(), RedisError> {
+        match self {
+            SortedIndex::Numeric => match parse_float(idx_val) {
+                Some(_) => Ok(()),
+                None => Err(RedisError::Str("ERR numeric index value must be a number!")),
+            },
+            SortedIndex::Lex if idx_val.contains(&LEX_TERMINATOR) => {
+                Err(RedisError::Str("ERR lex index value must not contain a NUL byte!"))
+            }
+            SortedIndex::Lex => Ok(()),
+            SortedIndex::Geo => {
+                let valid = coordinates(idx_val).map_or(false, |(lon, lat)| {
+                    let (lon, lat) = (parse_float(lon), parse_float(lat));
+                    lon.map_or(false, |lon| lon.abs() <= 180.0) && lat.map_or(false, |lat| lat.abs() <= MAX_LATITUDE)
+                });
+                if valid {
+                    Ok(())
+                } else {
+                    Err(RedisError::Str(GEO_VALUE_ERROR))
+                }
+            }
+        }
+    }
+}
+
+/// Kind of an index, which is the same for all the entries of a namespace: the keys of its values can't tell
+/// the kinds apart, so an index written as another kind would have its values split between both.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum IndexKind {
+    /// A set per index value.
+    Exact,
+    Sorted(SortedIndex),
+}
+
+impl IndexKind {
+    /// Name the kind gets recorded by, the keyword declaring it in case of sorted indices.
+    pub(super) fn name(self) -> &'static [u8] {
+        match self {
+            IndexKind::Exact => b"EXACT",
+            IndexKind::Sorted(kind) => kind.keyword(),
+        }
+    }
+
+    pub(super) fn parse_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"EXACT" => Some(IndexKind::Exact),
+            _ => SortedIndex::parse(name).map(IndexKind::Sorted),
+        }
+    }
+
+    pub(super) fn sorted(self) -> Option<SortedIndex> {
+        match self {
+            IndexKind::Exact => None,
+            IndexKind::Sorted(kind) => Some(kind),
+        }
+    }
+}
+
+impl From<Option<SortedIndex>> for IndexKind {
+    fn from(kind: Option<SortedIndex>) -> Self {
+        kind.map_or(IndexKind::Exact, IndexKind::Sorted)
+    }
+}
+
+/// Index value and key making up a member of a lex index, see `SortedIndex::member`.
+pub(super) fn split_lex_member(member: &[u8]) -> Option<(&[u8], &[u8])> {
+    let terminator = member.iter().position(|&b| b == LEX_TERMINATOR)?;
+    Some((&member[..terminator], &member[terminator + 1..]))
+}
+
+/// Longitude and latitude of a geo index value.
+pub(super) fn coordinates(idx_val: &[u8]) -> Option<(&[u8], &[u8])> {
+    let separator = idx_val.iter().position(|&b| b == COORDINATES_SEPARATOR)?;
+    Some((&idx_val[..separator], &idx_val[separator + 1..]))
+}
+
+/// Parses a float as strictly as Redis does (`string2d`) for the scores of `ZADD` and the coordinates of `GEOADD`:
+/// infinities only when spelled out as `inf`, no values overflowing to an infinity or underflowing to zero, and no NaN.
+fn parse_float(value: &[u8]) -> Option<f64> {
+    let value = std::str::from_utf8(value).ok()?;
+    let float: f64 = value.parse().ok()?;
+    let unsigned = value.strip_prefix(|c| c == '+' || c == '-').unwrap_or(value);
+    let valid = if float.is_infinite() {
+        unsigned.eq_ignore_ascii_case("inf")
+    } else if float == 0.0 {
+        let mantissa = unsigned.split(|c| c == 'e' || c == 'E').next().unwrap_or_default();
+        !mantissa.bytes().any(|b| (b'1'..=b'9').contains(&b))
+    } else {
+        !float.is_nan()
+    };
+    valid.then(|| float)
+}
+
+/// A namespace containing braces would break the hash tag, i.e. spread its keys over several slots
+/// and make them hash differently than the namespace argument itself, which is what clients route by.
+/// So would an empty one, as Redis hashes the whole key when its hash tag is empty (`{}`).
+pub(super) fn validate_namespace(namespace: &[u8]) -> Result<(), RedisError> {
+    if namespace.is_empty() {
+        Err(RedisError::Str("ERR namespace must not be empty!"))
+    } else if namespace.iter().any(|&b| b == TAG_OPEN || b == TAG_CLOSE) {
+        Err(RedisError::Str("ERR namespace must not contain '{' or '}'!"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Meta field recording one of the values of an index with several values (tags), e.g. `__tag:tag:rust`
+/// with the value itself as its value. Single values are recorded by a field named after the index instead.
+pub(super) fn tag_field(idx: &[u8], idx_val: &[u8]) -> Vec<u8> {
+    [TAG_FIELD_PREFIX, escape(idx).as_ref(), &[SEPARATOR], idx_val].concat()
+}
+
+/// Index name/value pairs of a meta hash (as replied by `HGETALL`), leaving out the other reserved fields.
+/// Indices with several values yield a pair for each of them.
+pub(super) fn index_pairs(meta: &[Vec<u8>]) -> impl Iterator<Item = (Cow<[u8]>, &[u8])> {
+    meta.chunks_exact(2).filter_map(|pair| {
+        let (field, idx_val) = (pair[0].as_slice(), pair[1].as_slice());
+        if let Some(tag) = field.strip_prefix(TAG_FIELD_PREFIX) {
+            split_component(tag).map(|(idx, _)| (idx, idx_val))
+        } else if field.starts_with(RESERVED_PREFIX) {
+            None
+        } else {
+            Some((Cow::Borrowed(field), idx_val))
+        }
+    })
+}
+
+/// Sorted index kind, name and value triples of a meta hash (as replied by `HGETALL`).
+pub(super) fn sorted_index_pairs(meta: &[Vec<u8>]) -> impl Iterator<Item = (SortedIndex, &[u8], &[u8])> {
+    meta.chunks_exact(2).filter_map(|pair| {
+        let (field, idx_val) = (pair[0].as_slice(), pair[1].as_slice());
+        SortedIndex::ALL
+            .iter()
+            .find_map(|kind| field.strip_prefix(kind.field_prefix()).map(|idx| (*kind, idx, idx_val)))
+    })
+}
+
+/// Redis key recognised as belonging to the module's layout (see `Namespaced`).
+pub(super) enum ModuleKey<'a> {
+    Data {
+        namespace: &'a [u8],
+        key: &'a [u8],
+    },
+    Meta {
+        namespace: &'a [u8],
+        key: &'a [u8],
+    },
+    Index {
+        namespace: &'a [u8],
+        idx: Cow<'a, [u8]>,
+        idx_val: &'a [u8],
+    },
+    SortedIndex {
+        kind: SortedIndex,
+        namespace: &'a [u8],
+        idx: &'a [u8],
+    },
+    IndexKinds {
+        namespace: &'a [u8],
+    },
+}
+
+pub(super) fn parse_key(key: &[u8]) -> Option<ModuleKey> {
+    if let Some(rest) = key.strip_prefix(INDEX_PREFIX) {
+        let (namespace, rest) = split_namespace(rest)?;
+        let (idx, idx_val) = split_component(rest)?;
+        return Some(ModuleKey::Index { namespace, idx, idx_val });
+    }
+    for kind in SortedIndex::ALL {
+        if let Some(rest) = key.strip_prefix(kind.key_prefix()) {
+            let (namespace, idx) = split_namespace(rest)?;
+            return Some(ModuleKey::SortedIndex { kind, namespace, idx });
+        }
+    }
+    if let Some(rest) = key.strip_prefix(INDEX_KINDS_PREFIX) {
+        let namespace = rest.strip_prefix(&[TAG_OPEN])?.strip_suffix(&[TAG_CLOSE])?;
+        if namespace.iter().any(|&b| b == TAG_OPEN || b == TAG_CLOSE) {
+            return None;
+        }
+        return Some(ModuleKey::IndexKinds { namespace });
+    }
+    if let Some(rest) = key.strip_prefix(META_PREFIX) {
+        let (namespace, key) = split_namespace(rest)?;
+        return Some(ModuleKey::Meta { namespace, key });
+    }
+    let (namespace, key) = split_namespace(key)?;
+    Some(ModuleKey::Data { namespace, key })
+}
+
+/// Splits `key` into the namespace within the leading hash tag and the remainder following the separator.
+fn split_namespace(key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let tagged = key.strip_prefix(&[TAG_OPEN])?;
+    let tag_end = tagged.iter().position(|&b| b == TAG_CLOSE)?;
+    let rest = tagged[tag_end + 1..].strip_prefix(&[SEPARATOR])?;
+    Some((&tagged[..tag_end], rest))
+}
+
+pub(super) fn escape(component: &[u8]) -> Cow<[u8]> {
+    if !component.iter().any(|&b| b == SEPARATOR || b == ESCAPE) {
+        return Cow::Borrowed(component);
+    }
+    let mut escaped = Vec::with_capacity(component.len() + 1);
+    for &b in component {
+        if b == SEPARATOR || b == ESCAPE {
+            escaped.push(ESCAPE);
+        }
+        escaped.push(b);
+    }
+    Cow::Owned(escaped)
+}
+
+fn unescape(component: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(component.len());
+    let mut bytes = component.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            ESCAPE => unescaped.extend(bytes.next()),
+            _ => unescaped.push(b),
+        }
+    }
+    unescaped
+}
+
+/// Splits `key` at the first unescaped separator, returning the unescaped component and the remainder.
+fn split_component(key: &[u8]) -> Option<(Cow<[u8]>, &[u8])> {
+    let mut escaped = false;
+    let mut has_escapes = false;
+    for (i, &b) in key.iter().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if b == ESCAPE {
+            escaped = true;
+            has_escapes = true;
+        } else if b == SEPARATOR {
+            let component = &key[..i];
+            let component = if has_escapes {
+                Cow::Owned(unescape(component))
+            } else {
+                Cow::Borrowed(component)
+            };
+            return Some((component, &key[i + 1..]));
+        }
+    }
+    None
+}
+
+/// Escapes glob-style special characters so that `pattern` only matches itself in `SCAN ... MATCH`.
+pub(super) fn glob_escape(pattern: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(pattern.len());
+    for &b in pattern {
+        if matches!(b, b'*' | b'?' | b'[' | b']' | b'\\') {
+            escaped.push(b'\\');
+        }
+        escaped.push(b);
+    }
+    escaped
+}
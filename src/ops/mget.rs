@@ -0,0 +1,91 @@
+use itertools::Itertools;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use super::*;
+
+pub struct MultiGet<'a> {
+    ctx: &'a Context,
+    namespace: RedisString,
+    keys: Vec<RedisString>,
+    single: bool,
+    options: ReplyOptions,
+}
+
+impl<'a> MultiGet<'a> {
+    pub fn from_get(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        let mut args = args.into_iter().skip(1);
+
+        let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
+        let key = args.next_arg()?;
+        let options = ReplyOptions::parse(args)?;
+
+        Ok(MultiGet {
+            ctx,
+            namespace,
+            keys: vec![key],
+            single: true,
+            options,
+        })
+    }
+
+    pub fn from_mget(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        let mut args = args.into_iter().skip(1);
+
+        let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
+        let key_count = args.next_u64()? as usize;
+        if key_count == 0 || args.len() < key_count {
+            return Err(RedisError::WrongArity);
+        }
+        let keys = (&mut args).take(key_count).collect_vec();
+        let options = ReplyOptions::parse(args)?;
+
+        Ok(MultiGet {
+            ctx,
+            namespace,
+            keys,
+            single: false,
+            options,
+        })
+    }
+
+    pub fn process(&self) -> RedisResult {
+        let keys = self.keys.iter().map(RedisString::as_slice).collect_vec();
+        let values = self.values(&keys)?;
+        let hits = values.iter().filter(|value| !matches!(value, RedisValue::Null)).count() as i64;
+        let mut entries = keys
+            .iter()
+            .zip(values)
+            .map(|(key, value)| self.entry_reply(key, value, &self.options))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Metric::Gets.add(keys.len() as i64);
+        metrics::record(self.namespace(), |stats| {
+            stats.hits += hits;
+            stats.misses += keys.len() as i64 - hits;
+        });
+
+        if self.single {
+            Ok(entries.pop().unwrap_or(RedisValue::Null))
+        } else {
+            Ok(RedisValue::Array(entries))
+        }
+    }
+}
+
+impl Namespaced for MultiGet<'_> {
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
+    }
+}
+
+impl Contextual for MultiGet<'_> {
+    fn context(&self) -> &Context {
+        self.ctx
+    }
+}
+
+impl CleanOperation for MultiGet<'_> {}
+
+impl EntryReply for MultiGet<'_> {}
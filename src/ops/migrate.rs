@@ -0,0 +1,111 @@
+use redis_module::{Context, LogLevel, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use super::*;
+
+/// Moves entries of a namespace written with the legacy key layout (without hash tags and escaping)
+/// over to the current one.
+///
+/// Only entries having a legacy meta hash get moved, as nothing else tells the module's keys apart from
+/// the keys of other applications sharing the instance (entries without any index had no meta).
+///
+/// The legacy layout is ambiguous for namespaces containing `:` (`meta_a:b:k` is key `k` of namespace `a:b`
+/// as well as key `b:k` of namespace `a`), so an entry is only attributed to the namespace when its legacy
+/// index sets of the namespace list it. Entries which can't be attributed are left in place and reported.
+pub struct Migrate<'a> {
+    ctx: &'a Context,
+    namespace: RedisString,
+}
+
+impl<'a> Migrate<'a> {
+    pub fn from(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        let mut args = args.into_iter().skip(1);
+
+        let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
+        args.done()?;
+
+        Ok(Migrate { ctx, namespace })
+    }
+
+    pub fn process(&self) -> RedisResult {
+        let mut migrated = 0;
+        let mut ambiguous = 0;
+
+        let legacy_meta_prefix = [META_PREFIX, self.namespace(), &[SEPARATOR]].concat();
+        for legacy_meta_key in self.scan_all(&[glob_escape(&legacy_meta_prefix).as_slice(), b"*"].concat())? {
+            let key = &legacy_meta_key[legacy_meta_prefix.len()..];
+            let meta = self.hgetall(&legacy_meta_key)?;
+            if !self.lists_key(&meta, key)? {
+                self.ctx.log(
+                    LogLevel::Warning,
+                    &format!(
+                        "Not migrating [ {} ], it isn't listed by the index sets of namespace [ {} ]",
+                        String::from_utf8_lossy(&legacy_meta_key),
+                        String::from_utf8_lossy(self.namespace())
+                    ),
+                );
+                ambiguous += 1;
+                continue;
+            }
+            for pair in meta.chunks_exact(2) {
+                let idx = &pair[0];
+                let idx_val = &pair[1];
+                self.move_member(&self.legacy_prefixed_idx(idx, idx_val), &self.prefixed_idx(idx, idx_val), key)?;
+            }
+            self.rename(&legacy_meta_key, &self.prefixed_meta(key))?;
+            self.rename(&self.legacy_prefixed(key), &self.prefixed(key))?;
+            migrated += 1;
+        }
+
+        Ok(RedisValue::Array(vec![
+            RedisValue::BulkString("migrated".to_string()),
+            RedisValue::Integer(migrated),
+            RedisValue::BulkString("ambiguous".to_string()),
+            RedisValue::Integer(ambiguous),
+        ]))
+    }
+
+    /// Whether any of the legacy index sets of the namespace named by the meta has the key as a member.
+    fn lists_key(&self, meta: &[Vec<u8>], key: &[u8]) -> Result<bool, RedisError> {
+        for pair in meta.chunks_exact(2) {
+            if self.sismember(&self.legacy_prefixed_idx(&pair[0], &pair[1]), key)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn legacy_prefixed(&self, key: &[u8]) -> Vec<u8> {
+        [self.namespace(), &[SEPARATOR], key].concat()
+    }
+
+    fn legacy_prefixed_idx(&self, idx: &[u8], idx_val: &[u8]) -> Vec<u8> {
+        [INDEX_PREFIX, self.namespace(), &[SEPARATOR], idx, &[SEPARATOR], idx_val].concat()
+    }
+
+    fn rename(&self, from: &[u8], to: &[u8]) -> Result<(), RedisError> {
+        if from != to && self.exists(from)? {
+            self.call::<()>("RENAME", &[from, to])?;
+        }
+        Ok(())
+    }
+
+    fn move_member(&self, from: &[u8], to: &[u8], member: &[u8]) -> Result<(), RedisError> {
+        if from != to {
+            self.call::<()>("SMOVE", &[from, to, member])?;
+        }
+        Ok(())
+    }
+}
+
+impl Namespaced for Migrate<'_> {
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
+    }
+}
+
+impl Contextual for Migrate<'_> {
+    fn context(&self) -> &Context {
+        self.ctx
+    }
+}
@@ -1,64 +1,54 @@
+use crate::binary;
+use crate::metrics::{self, Metric};
 use redis_module::{Context, RedisError, RedisResult, RedisValue, REDIS_OK};
 
+mod fsck;
+mod geo;
 mod get;
 mod groom;
+mod layout;
+mod mget;
+mod migrate;
+mod query;
+mod range;
 mod rem;
 mod rem_by_index;
+mod reply;
 mod set;
+mod stats;
+mod update_indices;
 
+use layout::*;
+use reply::*;
+
+/// Error code of writes expecting another version of an entry than the current one.
+const VERSION_MISMATCH: &str = "VERSIONMISMATCH";
+
+pub use fsck::Fsck;
+pub use geo::GeoSearch;
 pub use get::Get;
 pub use groom::EventGroom;
+pub use mget::MultiGet;
+pub use migrate::Migrate;
+pub use query::Query;
+pub use range::Range;
 pub use rem::Remove;
 pub use rem_by_index::RemoveByIndex;
+pub use reply::enable_lazy_repair;
 pub use set::Set;
+pub use stats::Stats;
+pub use update_indices::UpdateIndices;
 
-const SEPARATOR: char = ':';
-const META_PREFIX: &str = "meta_";
-const INDEX_PREFIX: &str = "idx_";
-
-trait Namespaced {
-    fn namespace(&self) -> &str;
-
-    fn prefixed(&self, key: &str) -> String {
-        format!("{}{separator}{}", self.namespace(), key, separator = SEPARATOR)
-    }
-
-    fn prefixed_meta(&self, key: &str) -> String {
-        format!(
-            "{meta}{}{separator}{}",
-            self.namespace(),
-            key,
-            meta = META_PREFIX,
-            separator = SEPARATOR
-        )
-    }
-
-    fn prefixed_idx(&self, idx: &str, idx_val: &str) -> String {
-        format!(
-            "{index}{}{separator}{}{separator}{}",
-            self.namespace(),
-            idx,
-            idx_val,
-            index = INDEX_PREFIX,
-            separator = SEPARATOR
-        )
-    }
-}
-
-fn split_namespace(key: &str) -> (&str, &str) {
-    let parts: Vec<&str> = key.split(SEPARATOR).collect();
-    (parts[0], parts[1])
-}
-
-fn is_string(v: RedisValue) -> Option<String> {
+fn is_string(v: RedisValue) -> Option<Vec<u8>> {
     match v {
-        RedisValue::SimpleString(s) => Some(s),
-        RedisValue::BulkString(s) => Some(s),
+        RedisValue::SimpleString(s) => Some(s.into_bytes()),
+        RedisValue::BulkString(s) => Some(s.into_bytes()),
+        RedisValue::StringBuffer(s) => Some(s),
         _ => None,
     }
 }
 
-fn extract_strings(mut values: Vec<RedisValue>) -> Vec<String> {
+fn extract_strings(mut values: Vec<RedisValue>) -> Vec<Vec<u8>> {
     values.drain(..).filter_map(is_string).collect()
 }
 
@@ -72,6 +62,12 @@ impl IntoRedisResult<()> for RedisValue {
     }
 }
 
+impl IntoRedisResult<RedisValue> for RedisValue {
+    fn into_redis_result(self) -> Result<RedisValue, RedisError> {
+        Ok(self)
+    }
+}
+
 impl IntoRedisResult<i64> for RedisValue {
     fn into_redis_result(self) -> Result<i64, RedisError> {
         if let RedisValue::Integer(n) = self {
@@ -88,8 +84,23 @@ impl IntoRedisResult<bool> for RedisValue {
     }
 }
 
-impl IntoRedisResult<Vec<String>> for RedisValue {
-    fn into_redis_result(self) -> Result<Vec<String>, RedisError> {
+impl IntoRedisResult<Vec<u8>> for RedisValue {
+    fn into_redis_result(self) -> Result<Vec<u8>, RedisError> {
+        is_string(self).ok_or_else(|| RedisError::String("command didn't return a string!".to_string()))
+    }
+}
+
+impl IntoRedisResult<Option<Vec<u8>>> for RedisValue {
+    fn into_redis_result(self) -> Result<Option<Vec<u8>>, RedisError> {
+        match self {
+            RedisValue::Null => Ok(None),
+            value => value.into_redis_result().map(Some),
+        }
+    }
+}
+
+impl IntoRedisResult<Vec<Vec<u8>>> for RedisValue {
+    fn into_redis_result(self) -> Result<Vec<Vec<u8>>, RedisError> {
         if let RedisValue::Array(values) = self {
             Ok(extract_strings(values))
         } else {
@@ -98,93 +109,214 @@ impl IntoRedisResult<Vec<String>> for RedisValue {
     }
 }
 
+impl IntoRedisResult<Vec<bool>> for RedisValue {
+    fn into_redis_result(self) -> Result<Vec<bool>, RedisError> {
+        if let RedisValue::Array(values) = self {
+            values.into_iter().map(RedisValue::into_redis_result).collect()
+        } else {
+            Err(RedisError::String("command didn't return a list of integers!".to_string()))
+        }
+    }
+}
+
+/// Reply of the `SCAN` family of commands: the next cursor and a batch of elements.
+impl IntoRedisResult<(Vec<u8>, Vec<Vec<u8>>)> for RedisValue {
+    fn into_redis_result(self) -> Result<(Vec<u8>, Vec<Vec<u8>>), RedisError> {
+        if let RedisValue::Array(mut values) = self {
+            if let (Some(elements), Some(cursor)) = (values.pop(), values.pop().and_then(is_string)) {
+                return Ok((cursor, elements.into_redis_result()?));
+            }
+        }
+        Err(RedisError::String("command didn't return a scan cursor!".to_string()))
+    }
+}
+
 trait Contextual {
     fn context(&self) -> &Context;
 
-    fn call<R>(&self, cmd: &str, args: &[&str]) -> Result<R, RedisError>
+    fn call<R>(&self, cmd: &str, args: &[&[u8]]) -> Result<R, RedisError>
     where
         RedisValue: IntoRedisResult<R>,
     {
-        self.context().call(cmd, args)?.into_redis_result()
+        binary::call(self.context(), cmd, args)?.into_redis_result()
     }
 
-    fn exists(&self, key: &str) -> Result<bool, RedisError> {
+    fn exists(&self, key: &[u8]) -> Result<bool, RedisError> {
         self.call("EXISTS", &[key])
     }
 
-    fn set(&self, key: &str, value: &str) -> Result<(), RedisError> {
-        self.call("SET", &[key, value])
+    fn key_type(&self, key: &[u8]) -> Result<Vec<u8>, RedisError> {
+        self.call("TYPE", &[key])
     }
 
-    fn incr(&self, key: &str) -> Result<i64, RedisError> {
-        self.call("INCR", &[key])
+    /// Fails unless the key is missing or holds the `expected` type, to be checked before writing
+    /// anything that could fail half way through.
+    fn expect_type(&self, key: &[u8], expected: &str) -> Result<(), RedisError> {
+        let actual = self.key_type(key)?;
+        if actual == b"none" || actual == expected.as_bytes() {
+            Ok(())
+        } else {
+            Err(RedisError::String(format!(
+                "ERR key [ {} ] holds a {} instead of a {}",
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(&actual),
+                expected
+            )))
+        }
     }
 
-    fn smembers(&self, key: &str) -> Result<Vec<String>, RedisError> {
+    fn smembers(&self, key: &[u8]) -> Result<Vec<Vec<u8>>, RedisError> {
         self.call("SMEMBERS", &[key])
     }
 
-    fn srandmember(&self, key: &str, n: usize) -> Result<Vec<String>, RedisError> {
-        self.call("SRANDMEMBER", &[key, &n.to_string()])
+    fn sscan(&self, key: &[u8], cursor: &[u8], count: u64) -> Result<(Vec<u8>, Vec<Vec<u8>>), RedisError> {
+        self.call("SSCAN", &[key, cursor, b"COUNT", count.to_string().as_bytes()])
+    }
+
+    fn srandmember(&self, key: &[u8], n: usize) -> Result<Vec<Vec<u8>>, RedisError> {
+        self.call("SRANDMEMBER", &[key, n.to_string().as_bytes()])
+    }
+
+    fn sismember(&self, key: &[u8], value: &[u8]) -> Result<bool, RedisError> {
+        self.call("SISMEMBER", &[key, value])
     }
 
-    fn srem(&self, key: &str, value: &str) -> Result<i64, RedisError> {
+    /// Requires Redis 6.2 or newer.
+    fn smismember(&self, key: &[u8], values: &[&[u8]]) -> Result<Vec<bool>, RedisError> {
+        self.call("SMISMEMBER", &[&[key][..], values].concat())
+    }
+
+    fn srem(&self, key: &[u8], value: &[u8]) -> Result<i64, RedisError> {
         self.call("SREM", &[key, value])
     }
 
-    fn hgetall(&self, key: &str) -> Result<Vec<String>, RedisError> {
+    fn hget(&self, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>, RedisError> {
+        self.call("HGET", &[key, field])
+    }
+
+    fn hgetall(&self, key: &[u8]) -> Result<Vec<Vec<u8>>, RedisError> {
         self.call("HGETALL", &[key])
     }
+
+    /// Collects all keys matching `pattern`, use only for administrative commands.
+    fn scan_all(&self, pattern: &[u8]) -> Result<Vec<Vec<u8>>, RedisError> {
+        let mut keys = vec![];
+        let mut cursor = b"0".to_vec();
+        loop {
+            let (next, batch): (Vec<u8>, Vec<Vec<u8>>) = self.call("SCAN", &[&cursor, b"MATCH", pattern, b"COUNT", b"1000"])?;
+            keys.extend(batch);
+            if next == b"0" {
+                return Ok(keys);
+            }
+            cursor = next;
+        }
+    }
 }
 
 trait CleanOperation: Contextual + Namespaced {
-    fn del(&self, key: &str) -> Result<bool, RedisError> {
+    fn del(&self, key: &[u8]) -> Result<bool, RedisError> {
         self.call("DEL", &[key])
     }
 
-    fn clean_key(&self, key: &str) -> Result<(), RedisError> {
+    fn clean_key(&self, key: &[u8]) -> Result<(), RedisError> {
         self.del(&self.prefixed(key))?;
 
+        if !self.clean_meta(key)? {
+            Metric::MetaMissing.incr();
+        }
+        Ok(())
+    }
+
+    /// Removes the key from the index (and sorted index) sets listed in its meta along with the meta itself,
+    /// returns whether there was any meta.
+    fn clean_meta(&self, key: &[u8]) -> Result<bool, RedisError> {
         let meta_key = self.prefixed_meta(key);
         let meta = self.hgetall(&meta_key)?;
         if meta.is_empty() {
-            self.incr("meta_missing")?;
-        } else {
-            for pair in meta.chunks_exact(2) {
-                let idx = &pair[0];
-                let idx_val = &pair[1];
-                self.rm_from_index(key, idx, idx_val)?;
-            }
-            self.del(&meta_key)?;
+            return Ok(false);
         }
-        Ok(())
+
+        for (idx, idx_val) in index_pairs(&meta) {
+            self.rm_from_index(key, &idx, idx_val)?;
+        }
+        for (kind, idx, idx_val) in sorted_index_pairs(&meta) {
+            self.rm_from_sorted_index(key, kind, idx, idx_val)?;
+        }
+        self.del(&meta_key)?;
+        Ok(true)
     }
 
-    fn rm_from_index(&self, key: &str, idx: &str, idx_val: &str) -> RedisResult {
-        self.srem(&self.prefixed_idx(idx, idx_val), key)?;
+    /// Version of the entry's last write, `0` for entries not present (or written before the module kept versions).
+    fn version(&self, key: &[u8]) -> Result<i64, RedisError> {
+        let version = self.hget(&self.prefixed_meta(key), VERSION_FIELD)?;
+        Ok(version
+            .and_then(|version| String::from_utf8(version).ok()?.parse().ok())
+            .unwrap_or(0))
+    }
+
+    /// Last version handed out within the namespace, `0` before its first write.
+    fn last_version(&self) -> Result<i64, RedisError> {
+        let version = self.call::<Option<Vec<u8>>>("GET", &[&self.prefixed_version_counter()])?;
+        Ok(version
+            .and_then(|version| String::from_utf8(version).ok()?.parse().ok())
+            .unwrap_or(0))
+    }
+
+    /// Draws the version of a write from the namespace's counter, so that versions never repeat within
+    /// the namespace, not even for an entry removed (or expired) and created again.
+    fn next_version(&self) -> Result<i64, RedisError> {
+        self.call("INCR", &[&self.prefixed_version_counter()])
+    }
+
+    /// Kind `idx` got recorded as when first written within the namespace, `None` for indices not written yet
+    /// (or only by versions of the module not recording the kinds).
+    fn index_kind(&self, idx: &[u8]) -> Result<Option<IndexKind>, RedisError> {
+        Ok(self
+            .hget(&self.prefixed_index_kinds(), idx)?
+            .and_then(|name| IndexKind::parse_name(&name)))
+    }
+
+    /// Fails if `idx` got recorded as another kind of index, to be checked before writing anything.
+    fn expect_index_kind(&self, idx: &[u8], kind: IndexKind) -> Result<(), RedisError> {
+        match self.index_kind(idx)? {
+            Some(recorded) if recorded != kind => Err(RedisError::String(format!(
+                "ERR index [ {} ] is a {} index, not {}!",
+                String::from_utf8_lossy(idx),
+                String::from_utf8_lossy(recorded.name()),
+                String::from_utf8_lossy(kind.name())
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records the kind of `idx` unless recorded already.
+    fn record_index_kind(&self, idx: &[u8], kind: IndexKind) -> RedisResult {
+        self.call::<()>("HSETNX", &[&self.prefixed_index_kinds(), idx, kind.name()])?;
 
         REDIS_OK
     }
-}
 
-pub struct Init<'a> {
-    ctx: &'a Context,
-}
+    fn rm_from_index(&self, key: &[u8], idx: &[u8], idx_val: &[u8]) -> RedisResult {
+        self.srem(&self.prefixed_idx(idx, idx_val), key)?;
 
-impl<'a> Init<'a> {
-    pub fn from(ctx: &'a Context) -> Self {
-        Self { ctx }
+        REDIS_OK
     }
 
-    pub fn perform(&self) {
-        self.set("events", "0").unwrap();
-        self.set("keys_expired", "0").unwrap();
-        self.set("meta_missing", "0").unwrap();
+    fn add_to_sorted_index(&self, key: &[u8], kind: SortedIndex, idx: &[u8], idx_val: &[u8]) -> RedisResult {
+        let idx_key = self.prefixed_sorted_idx(kind, idx);
+        match kind {
+            SortedIndex::Numeric => self.call("ZADD", &[&idx_key, idx_val, key]),
+            SortedIndex::Lex => self.call("ZADD", &[&idx_key, b"0", &kind.member(key, idx_val)]),
+            SortedIndex::Geo => {
+                let (lon, lat) = coordinates(idx_val).ok_or(RedisError::Str(GEO_VALUE_ERROR))?;
+                self.call("GEOADD", &[&idx_key, lon, lat, key])
+            }
+        }
     }
-}
 
-impl<'a> Contextual for Init<'a> {
-    fn context(&self) -> &Context {
-        self.ctx
+    fn rm_from_sorted_index(&self, key: &[u8], kind: SortedIndex, idx: &[u8], idx_val: &[u8]) -> RedisResult {
+        self.call::<()>("ZREM", &[&self.prefixed_sorted_idx(kind, idx), &kind.member(key, idx_val)])?;
+
+        REDIS_OK
     }
 }
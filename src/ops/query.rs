@@ -0,0 +1,136 @@
+use itertools::Itertools;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString};
+
+use super::*;
+
+enum Operator {
+    And,
+    Or,
+}
+
+/// Looks up entries matching several `idx = idx_val` predicates at once, computed by set operations
+/// over the index sets.
+pub struct Query<'a> {
+    ctx: &'a Context,
+    namespace: RedisString,
+    operator: Operator,
+    predicates: Vec<(RedisString, RedisString)>,
+    negated: Vec<(RedisString, RedisString)>,
+    options: ReplyOptions,
+}
+
+impl<'a> Query<'a> {
+    pub fn from(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        let mut args = args.into_iter().skip(1).peekable();
+
+        let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
+        let operator = match args.next_arg()?.as_slice().to_ascii_uppercase().as_slice() {
+            b"AND" => Operator::And,
+            b"OR" => Operator::Or,
+            _ => return Err(RedisError::Str("ERR operator must be one of AND, OR!")),
+        };
+        let predicates = parse_predicates(&mut args)?;
+
+        let mut negated = vec![];
+        if args.peek().map_or(false, |arg| arg.as_slice().eq_ignore_ascii_case(b"NOT")) {
+            args.next();
+            negated = parse_predicates(&mut args)?;
+        }
+
+        let options = ReplyOptions::parse_for_lookup(args)?;
+
+        Ok(Query {
+            ctx,
+            namespace,
+            operator,
+            predicates,
+            negated,
+            options,
+        })
+    }
+
+    pub fn process(&self) -> RedisResult {
+        let idx_keys = self.idx_keys(&self.predicates);
+        let command = match self.operator {
+            Operator::And => "SINTER",
+            Operator::Or => "SUNION",
+        };
+        let keys: Vec<Vec<u8>> = if self.negated.is_empty() {
+            self.call(command, &idx_keys.iter().map(Vec::as_slice).collect_vec())?
+        } else {
+            self.without_negated(&idx_keys)?
+        };
+
+        self.entries_reply(&keys, &idx_keys, &self.options)
+    }
+
+    /// The command being read-only (to be served by replicas too), the combination of the predicates can't be
+    /// stored for `SDIFF` to subtract the negated index sets from. An intersection is computed by `SINTER` and its
+    /// members checked against the negated index sets by `SMISMEMBER` (Redis 6.2 or newer) instead, so that just the
+    /// result leaves Redis. A union is joined from the differences of each of the index sets,
+    /// `(P_1 | ... | P_n) - N = (P_1 - N) | ... | (P_n - N)`.
+    fn without_negated(&self, idx_keys: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, RedisError> {
+        let negated_idx_keys = self.idx_keys(&self.negated);
+        match self.operator {
+            Operator::And => {
+                let mut keys: Vec<Vec<u8>> = self.call("SINTER", &idx_keys.iter().map(Vec::as_slice).collect_vec())?;
+                for negated_idx_key in &negated_idx_keys {
+                    if keys.is_empty() {
+                        break;
+                    }
+                    let negated = self.smismember(negated_idx_key, &keys.iter().map(Vec::as_slice).collect_vec())?;
+                    keys = keys
+                        .into_iter()
+                        .zip(negated)
+                        .filter(|(_, negated)| !negated)
+                        .map(|(key, _)| key)
+                        .collect();
+                }
+                Ok(keys)
+            }
+            Operator::Or => {
+                let differences = idx_keys
+                    .iter()
+                    .map(|idx_key| {
+                        let args = std::iter::once(idx_key).chain(&negated_idx_keys).map(Vec::as_slice).collect_vec();
+                        self.call::<Vec<Vec<u8>>>("SDIFF", &args)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(differences.into_iter().flatten().unique().collect())
+            }
+        }
+    }
+
+    fn idx_keys(&self, predicates: &[(RedisString, RedisString)]) -> Vec<Vec<u8>> {
+        predicates
+            .iter()
+            .map(|(idx, idx_val)| self.prefixed_idx(idx.as_slice(), idx_val.as_slice()))
+            .collect()
+    }
+}
+
+// <num_predicates> <idx_1> <idx_1_val> ... <idx_n> <idx_n_val>
+fn parse_predicates(args: &mut impl Iterator<Item = RedisString>) -> Result<Vec<(RedisString, RedisString)>, RedisError> {
+    let count = args.next_u64()? as usize;
+    if count == 0 {
+        return Err(RedisError::Str("ERR at least one predicate is required!"));
+    }
+    (0..count).map(|_| Ok((args.next_arg()?, args.next_arg()?))).collect()
+}
+
+impl Namespaced for Query<'_> {
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
+    }
+}
+
+impl Contextual for Query<'_> {
+    fn context(&self) -> &Context {
+        self.ctx
+    }
+}
+
+impl CleanOperation for Query<'_> {}
+
+impl EntryReply for Query<'_> {}
@@ -0,0 +1,154 @@
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString};
+
+use super::*;
+
+/// Looks up entries by a range of values of a numeric or lex index, ordered by the value.
+pub struct Range<'a> {
+    ctx: &'a Context,
+    namespace: RedisString,
+    idx: RedisString,
+    bounds: Bounds,
+    rev: bool,
+    limit: Option<(i64, i64)>,
+    options: ReplyOptions,
+}
+
+/// Index values looked up, the same as with `ZRANGEBYSCORE` (numeric indices) resp. `ZRANGEBYLEX` (lex indices)
+/// or all the values starting with a prefix (lex indices).
+enum Bounds {
+    Score(RedisString, RedisString),
+    Lex(RedisString, RedisString),
+    Prefix(RedisString),
+}
+
+impl<'a> Range<'a> {
+    pub fn from(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        let mut args = args.into_iter().skip(1);
+
+        let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
+        let idx = args.next_arg()?;
+        let first = args.next_arg()?;
+        let (prefix, min, max) = if first.as_slice().eq_ignore_ascii_case(b"PREFIX") {
+            (Some(args.next_arg()?), None, None)
+        } else {
+            (None, Some(first), Some(args.next_arg()?))
+        };
+
+        let mut by_lex = false;
+        let mut rev = false;
+        let mut limit = None;
+        let mut flags = vec![];
+        while let Some(arg) = args.next() {
+            match arg.as_slice().to_ascii_uppercase().as_slice() {
+                b"BYLEX" => by_lex = true,
+                b"REV" => rev = true,
+                b"LIMIT" => limit = Some((args.next_i64()?, args.next_i64()?)),
+                _ => flags.push(arg),
+            }
+        }
+        let options = ReplyOptions::parse_for_lookup(flags.into_iter())?;
+
+        // a NUL would let the bounds reach past the terminator into the keys of the members
+        let bounds = match (prefix, min, max) {
+            (Some(_), _, _) if by_lex => return Err(RedisError::Str("ERR syntax error")),
+            (Some(prefix), _, _) => {
+                SortedIndex::Lex.validate_value(prefix.as_slice())?;
+                Bounds::Prefix(prefix)
+            }
+            (None, Some(min), Some(max)) if by_lex => {
+                SortedIndex::Lex.validate_value(min.as_slice())?;
+                SortedIndex::Lex.validate_value(max.as_slice())?;
+                Bounds::Lex(min, max)
+            }
+            (None, Some(min), Some(max)) => Bounds::Score(min, max),
+            _ => return Err(RedisError::WrongArity),
+        };
+
+        Ok(Range {
+            ctx,
+            namespace,
+            idx,
+            bounds,
+            rev,
+            limit,
+            options,
+        })
+    }
+
+    pub fn process(&self) -> RedisResult {
+        let by_score = matches!(self.bounds, Bounds::Score(..));
+        let kind = if by_score { SortedIndex::Numeric } else { SortedIndex::Lex };
+        let idx_key = self.prefixed_sorted_idx(kind, self.idx.as_slice());
+        let (min, max) = match &self.bounds {
+            Bounds::Score(min, max) => (min.as_slice().to_vec(), max.as_slice().to_vec()),
+            Bounds::Lex(min, max) => (lex_bound(min.as_slice(), false)?, lex_bound(max.as_slice(), true)?),
+            Bounds::Prefix(prefix) => prefix_bounds(prefix.as_slice()),
+        };
+        let command = match (by_score, self.rev) {
+            (true, false) => "ZRANGEBYSCORE",
+            (true, true) => "ZREVRANGEBYSCORE",
+            (false, false) => "ZRANGEBYLEX",
+            (false, true) => "ZREVRANGEBYLEX",
+        };
+        let (from, to) = if self.rev { (&max, &min) } else { (&min, &max) };
+        let limit = self.limit.map(|(offset, count)| (offset.to_string(), count.to_string()));
+
+        let mut args = vec![idx_key.as_slice(), from.as_slice(), to.as_slice()];
+        if let Some((offset, count)) = &limit {
+            args.extend([&b"LIMIT"[..], offset.as_bytes(), count.as_bytes()]);
+        }
+        let members: Vec<Vec<u8>> = self.call(command, &args)?;
+
+        self.entries_reply(&members, &[idx_key], &self.options)
+    }
+}
+
+/// Translates a bound on index values (`[` inclusive or `(` exclusive value, `-` or `+`) into the bound
+/// on the members `<idx_val>\0<key>` of a lex index: the members of a value sort right after the value itself
+/// but before any longer value starting with it (which never continues by `\0`, only by `\x01` at the least).
+fn lex_bound(bound: &[u8], upper: bool) -> Result<Vec<u8>, RedisError> {
+    match (bound, upper) {
+        (b"-", _) | (b"+", _) => Ok(bound.to_vec()),
+        ([b'[', ..], false) | ([b'(', ..], true) => Ok(bound.to_vec()),
+        ([b'(', value @ ..], false) => Ok([&b"["[..], value, &[1]].concat()),
+        ([b'[', value @ ..], true) => Ok([&b"("[..], value, &[1]].concat()),
+        _ => Err(RedisError::Str("ERR min or max not valid string range item")),
+    }
+}
+
+/// Bounds on the members of a lex index covering all the values starting with `prefix`,
+/// up to (excluding) the least value greater than all of them.
+fn prefix_bounds(prefix: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    if prefix.is_empty() {
+        return (b"-".to_vec(), b"+".to_vec());
+    }
+    let mut successor = prefix.to_vec();
+    while successor.last() == Some(&u8::MAX) {
+        successor.pop();
+    }
+    let max = match successor.last_mut() {
+        Some(last) => {
+            *last += 1;
+            [&b"("[..], &successor].concat()
+        }
+        None => b"+".to_vec(),
+    };
+    ([&b"["[..], prefix].concat(), max)
+}
+
+impl Namespaced for Range<'_> {
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
+    }
+}
+
+impl Contextual for Range<'_> {
+    fn context(&self) -> &Context {
+        self.ctx
+    }
+}
+
+impl CleanOperation for Range<'_> {}
+
+impl EntryReply for Range<'_> {}
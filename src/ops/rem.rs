@@ -1,5 +1,3 @@
-use std::borrow::Borrow;
-
 use itertools::Itertools;
 use redis_module::{NextArg, RedisString};
 
@@ -16,6 +14,7 @@ impl<'a> Remove<'a> {
         let mut args = args.into_iter().skip(1);
 
         let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
         let keys = args.collect_vec();
 
         Ok(Remove { ctx, namespace, keys })
@@ -23,15 +22,17 @@ impl<'a> Remove<'a> {
 
     pub fn process(&self) -> RedisResult {
         for key in &self.keys {
-            self.clean_key(key.borrow())?;
+            self.clean_key(key.as_slice())?;
         }
+        Metric::Removes.add(self.keys.len() as i64);
+        metrics::record(self.namespace(), |stats| stats.removals += self.keys.len() as i64);
         Ok(RedisValue::Integer(self.keys.len() as i64))
     }
 }
 
 impl Namespaced for Remove<'_> {
-    fn namespace(&self) -> &str {
-        self.namespace.borrow()
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
     }
 }
 
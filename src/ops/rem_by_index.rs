@@ -1,5 +1,3 @@
-use std::borrow::Borrow;
-
 use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
 
 use super::*;
@@ -16,6 +14,7 @@ impl<'a> RemoveByIndex<'a> {
         let mut args = args.into_iter().skip(1);
 
         let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
         let idx = args.next_arg()?;
         let idx_val = args.next_arg()?;
 
@@ -28,7 +27,7 @@ impl<'a> RemoveByIndex<'a> {
     }
 
     pub fn process(&self) -> RedisResult {
-        let idx_key = &self.prefixed_idx(self.idx.borrow(), self.idx_val.borrow());
+        let idx_key = &self.prefixed_idx(self.idx.as_slice(), self.idx_val.as_slice());
         let keys = self.smembers(idx_key)?;
         if keys.is_empty() {
             Ok(RedisValue::Array(vec![]))
@@ -36,14 +35,16 @@ impl<'a> RemoveByIndex<'a> {
             for key in &keys {
                 self.clean_key(key)?;
             }
+            Metric::Removes.add(keys.len() as i64);
+            metrics::record(self.namespace(), |stats| stats.removals += keys.len() as i64);
             Ok(RedisValue::Integer(keys.len() as i64))
         }
     }
 }
 
 impl Namespaced for RemoveByIndex<'_> {
-    fn namespace(&self) -> &str {
-        self.namespace.borrow()
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
     }
 }
 
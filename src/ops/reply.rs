@@ -0,0 +1,175 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use itertools::Itertools;
+use redis_module::{RedisError, RedisResult, RedisString, RedisValue};
+
+use super::*;
+
+static LAZY_REPAIR: AtomicBool = AtomicBool::new(false);
+
+/// Lets lookups on primaries groom the members of index sets whose data key is gone (see `entries_reply`),
+/// as asked for by the `LAZY_REPAIR` module argument.
+pub fn enable_lazy_repair() {
+    LAZY_REPAIR.store(true, Ordering::Relaxed);
+}
+
+/// Optional extras of read commands, given as trailing flags, e.g. `... WITHTTL WITHINDICES`.
+#[derive(Default)]
+pub(super) struct ReplyOptions {
+    keys_only: bool,
+    with_keys: bool,
+    with_ttl: bool,
+    with_indices: bool,
+    with_version: bool,
+}
+
+impl ReplyOptions {
+    /// Options of commands reading entries by their keys.
+    pub(super) fn parse(args: impl Iterator<Item = RedisString>) -> Result<Self, RedisError> {
+        Self::parse_flags(args, false)
+    }
+
+    /// Options of commands looking entries up by index, which may also reply with just the keys (`KEYSONLY`)
+    /// or with each key preceding its entry (`WITHKEYS`).
+    pub(super) fn parse_for_lookup(args: impl Iterator<Item = RedisString>) -> Result<Self, RedisError> {
+        Self::parse_flags(args, true)
+    }
+
+    fn parse_flags(args: impl Iterator<Item = RedisString>, lookup: bool) -> Result<Self, RedisError> {
+        let mut options = Self::default();
+        for arg in args {
+            match arg.as_slice().to_ascii_uppercase().as_slice() {
+                b"KEYSONLY" if lookup => options.keys_only = true,
+                b"WITHKEYS" if lookup => options.with_keys = true,
+                b"WITHTTL" => options.with_ttl = true,
+                b"WITHINDICES" => options.with_indices = true,
+                b"WITHVERSION" => options.with_version = true,
+                _ => return Err(RedisError::Str("ERR syntax error")),
+            }
+        }
+        if options.keys_only && (options.with_keys || !options.is_plain()) {
+            return Err(RedisError::Str("ERR KEYSONLY can't be combined with other options!"));
+        }
+        Ok(options)
+    }
+
+    /// Whether entries are replied as bare values.
+    fn is_plain(&self) -> bool {
+        !self.with_ttl && !self.with_indices && !self.with_version
+    }
+}
+
+/// Builds replies for entries read from the namespace according to the `ReplyOptions`.
+pub(super) trait EntryReply: CleanOperation {
+    fn values(&self, keys: &[&[u8]]) -> Result<Vec<RedisValue>, RedisError> {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let prefixed_keys = keys.iter().map(|k| self.prefixed(k)).collect_vec();
+        let prefixed_keys_slices = prefixed_keys.iter().map(Vec::as_slice).collect_vec();
+        match self.call("MGET", prefixed_keys_slices.as_slice())? {
+            RedisValue::Array(values) => Ok(values),
+            _ => Err(RedisError::Str("ERR MGET didn't return an array!")),
+        }
+    }
+
+    /// Replies entries looked up by an index (given its members, which are the keys themselves but for a lex
+    /// index), either as the keys themselves or as entries, optionally each preceded by its key (a flat array
+    /// of pairs the same as `HGETALL`).
+    ///
+    /// Members whose data key is gone without the module noticing (e.g. plain `DEL`) are left out of replies
+    /// reading the values (`KEYSONLY` lists them as they are). Given `LAZY_REPAIR`, primaries groom them on the
+    /// spot as well, including removal from the `idx_keys` they were looked up in.
+    fn entries_reply(&self, members: &[Vec<u8>], idx_keys: &[Vec<u8>], options: &ReplyOptions) -> RedisResult {
+        let lex = idx_keys.iter().any(|idx_key| idx_key.starts_with(LEX_INDEX_PREFIX));
+        let members = members
+            .iter()
+            .filter_map(|member| {
+                let key = if lex { split_lex_member(member)?.1 } else { member.as_slice() };
+                Some((key, member.as_slice()))
+            })
+            .collect_vec();
+        let keys = members.iter().map(|(key, _)| *key).collect_vec();
+        let repair = LAZY_REPAIR.load(Ordering::Relaxed) && !binary::is_replica(self.context());
+
+        let mut reply = Vec::with_capacity(if options.with_keys { 2 * keys.len() } else { keys.len() });
+        if options.keys_only {
+            for (key, member) in members {
+                // the values aren't read, so the data keys only need to be checked for members to repair
+                if repair && !self.exists(&self.prefixed(key))? {
+                    self.repair_stale_member(key, member, idx_keys)?;
+                } else {
+                    reply.push(RedisValue::StringBuffer(key.to_vec()));
+                }
+            }
+        } else {
+            for ((key, member), value) in members.into_iter().zip(self.values(&keys)?) {
+                if matches!(value, RedisValue::Null) {
+                    if repair {
+                        self.repair_stale_member(key, member, idx_keys)?;
+                    }
+                    continue;
+                }
+                if options.with_keys {
+                    reply.push(RedisValue::StringBuffer(key.to_vec()));
+                }
+                reply.push(self.entry_reply(key, value, options)?);
+            }
+        }
+
+        if reply.is_empty() {
+            Metric::IndexMisses.incr();
+            metrics::record(self.namespace(), |stats| stats.misses += 1);
+        } else {
+            Metric::IndexHits.incr();
+            metrics::record(self.namespace(), |stats| stats.hits += 1);
+        }
+        Ok(RedisValue::Array(reply))
+    }
+
+    fn repair_stale_member(&self, key: &[u8], member: &[u8], idx_keys: &[Vec<u8>]) -> Result<(), RedisError> {
+        self.clean_key(key)?;
+        // in case the meta is gone as well clean_key can't tell which index sets to groom
+        for idx_key in idx_keys {
+            if idx_key.starts_with(INDEX_PREFIX) {
+                self.srem(idx_key, member)?;
+            } else {
+                self.call::<()>("ZREM", &[idx_key, member])?;
+            }
+        }
+        Metric::StaleMembersRepaired.incr();
+        Ok(())
+    }
+
+    /// Missing entries are always replied as `nil`, existing ones as a bare value unless any option is set,
+    /// in which case the value is followed by the TTL (in seconds), index name/value pairs and/or the version.
+    fn entry_reply(&self, key: &[u8], value: RedisValue, options: &ReplyOptions) -> Result<RedisValue, RedisError> {
+        if options.is_plain() || matches!(value, RedisValue::Null) {
+            return Ok(value);
+        }
+
+        let mut entry = vec![value];
+        if options.with_ttl {
+            entry.push(RedisValue::Integer(self.call("TTL", &[&self.prefixed(key)])?));
+        }
+        if options.with_indices {
+            let meta = self.hgetall(&self.prefixed_meta(key))?;
+            entry.push(RedisValue::Array(
+                index_pairs(&meta)
+                    .chain(sorted_index_pairs(&meta).map(|(_, idx, idx_val)| (Cow::Borrowed(idx), idx_val)))
+                    .flat_map(|(idx, idx_val)| {
+                        [
+                            RedisValue::StringBuffer(idx.into_owned()),
+                            RedisValue::StringBuffer(idx_val.to_vec()),
+                        ]
+                    })
+                    .collect(),
+            ));
+        }
+        if options.with_version {
+            entry.push(RedisValue::Integer(self.version(key)?));
+        }
+        Ok(RedisValue::Array(entry))
+    }
+}
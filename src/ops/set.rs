@@ -1,97 +1,559 @@
-use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::iter::Peekable;
 
-use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, REDIS_OK};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue, REDIS_OK};
 
-use itertools::{interleave, Itertools};
+use itertools::Itertools;
 
 use super::*;
 
 pub struct Set<'a> {
     ctx: &'a Context,
     namespace: RedisString,
-    expiry: RedisString,
-    indices: Vec<RedisString>,
-    kv_index_lines: Vec<RedisString>,
+    expiry: BatchExpiry,
+    condition: BatchCondition,
+    /// Each line carries the version of the entry it expects to overwrite (right after the other per-line fields).
+    cas: bool,
+    /// Index names declared to be backed by a sorted set rather than by a set per value.
+    sorted_indices: Vec<(RedisString, SortedIndex)>,
+    indices: Indices,
+    kv_index_lines: Vec<Vec<RedisString>>,
+}
+
+/// Index names of the batch, either common to all the lines (each line giving just the values)
+/// or given on each line as index name/value pairs.
+enum Indices {
+    Common(Vec<RedisString>),
+    PerLine,
 }
 
 impl<'a> Set<'a> {
-    pub fn from(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
-        let mut args = args.into_iter().skip(1);
+    pub fn from_msetex(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        Self::from(ctx, args, true)
+    }
 
-        let namespace = args.next_arg()?;
-        let expiry = args.next_arg()?;
-        let index_count = args.next_u64()? as usize;
+    /// Entries written without expiry persist until removed explicitly (or evicted).
+    pub fn from_mset(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        Self::from(ctx, args, false)
+    }
 
-        if args.len() < index_count {
-            return Err(RedisError::WrongArity);
-        }
+    fn from(ctx: &'a Context, args: Vec<RedisString>, with_expiry: bool) -> Result<Self, RedisError> {
+        let mut args = args.into_iter().skip(1).peekable();
 
-        if (args.len() - index_count) % (index_count + 2) != 0 {
-            return Err(RedisError::WrongArity);
+        let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
+        let expiry = if with_expiry {
+            BatchExpiry::parse(&mut args)?
+        } else {
+            BatchExpiry::Common(Expiry::Persist)
+        };
+        let condition = BatchCondition::parse(&mut args);
+        let cas = args.peek().map_or(false, |arg| arg.as_slice().eq_ignore_ascii_case(b"CAS"));
+        if cas {
+            args.next();
+            if !matches!(condition, BatchCondition::Common(Condition::Always)) {
+                return Err(RedisError::Str("ERR CAS can't be combined with NX, XX or CONDITIONAL!"));
+            }
         }
+        let sorted_indices = parse_sorted_indices(&mut args)?;
+        // key, value and the per-line fields preceding the indices
+        let line_fields = 2 + expiry.line_fields() + condition.line_fields() + cas as usize;
 
-        let indices = (&mut args).take(index_count).map_into().collect_vec();
-        if indices.len() != indices.iter().unique().count() {
-            return Err(RedisError::Str("ERR index names must be unique!"));
-        }
+        let (indices, kv_index_lines) = if args.peek().map_or(false, |arg| arg.as_slice().eq_ignore_ascii_case(b"PAIRS")) {
+            args.next();
+            (Indices::PerLine, parse_lines_with_pairs(&mut args, line_fields)?)
+        } else {
+            let index_count = args.next_u64()? as usize;
+
+            if args.len() < index_count {
+                return Err(RedisError::WrongArity);
+            }
+
+            let line_width = line_fields + index_count;
+            if (args.len() - index_count) % line_width != 0 {
+                return Err(RedisError::WrongArity);
+            }
+
+            let indices = (&mut args).take(index_count).collect_vec();
+            if indices.iter().map(RedisString::as_slice).unique().count() != indices.len() {
+                return Err(RedisError::Str("ERR index names must be unique!"));
+            }
+            validate_index_names(indices.iter().map(RedisString::as_slice))?;
 
-        let kv_index_lines = args.map_into().collect_vec();
+            let kv_index_lines: Vec<Vec<RedisString>> = args.chunks(line_width).into_iter().map(|line| line.collect()).collect();
+            (Indices::Common(indices), kv_index_lines)
+        };
 
         Ok(Self {
             ctx,
             namespace,
             expiry,
+            condition,
+            cas,
+            sorted_indices,
             indices,
             kv_index_lines,
         })
     }
 
+    /// The whole batch gets validated before anything is written, so that a failing line
+    /// leaves the namespace untouched rather than with the preceding lines already applied.
+    ///
+    /// Conditional batches reply whether each of the lines got written (`1`) or skipped (`0`).
     pub fn process(&self) -> RedisResult {
-        for kv_index_line in self.kv_index_lines.chunks(2 + self.indices.len()) {
-            self.process_kv_line(kv_index_line)?;
+        let index_kinds = self.index_kinds();
+        for (idx, kind) in &index_kinds {
+            self.expect_index_kind(idx, *kind)?;
+        }
+        for (line, kv_index_line) in self.kv_index_lines.iter().enumerate() {
+            self.validate_kv_line(kv_index_line)
+                .map_err(|e| line_error(line, kv_index_line, e))?;
+        }
+        if self.cas {
+            self.check_versions()?;
+        }
+        for (idx, kind) in index_kinds {
+            self.record_index_kind(idx, kind)?;
         }
 
-        REDIS_OK
+        let mut written = Vec::with_capacity(self.kv_index_lines.len());
+        for (line, kv_index_line) in self.kv_index_lines.iter().enumerate() {
+            written.push(
+                self.process_kv_line(kv_index_line)
+                    .map_err(|e| line_error(line, kv_index_line, e))?,
+            );
+        }
+        Metric::Sets.add(written.iter().filter(|&&w| w).count() as i64);
+
+        if let BatchCondition::Common(Condition::Always) = self.condition {
+            REDIS_OK
+        } else {
+            Ok(RedisValue::Array(
+                written.into_iter().map(|w| RedisValue::Integer(w as i64)).collect(),
+            ))
+        }
+    }
+
+    /// Checks the line against everything that could make `process_kv_line` fail half way through,
+    /// i.e. keys the line touches already holding a different data type.
+    fn validate_kv_line(&self, kv_index_line: &[RedisString]) -> Result<(), RedisError> {
+        let key = kv_index_line[0].as_slice();
+
+        self.expect_type(&self.prefixed(key), "string")?;
+        // skipped unless the key exists in case of XX
+        let condition = self.condition_of_line(kv_index_line)?;
+        if let Expiry::KeepTtl = self.expiry.of_line(kv_index_line)? {
+            if condition != Condition::IfExists && !self.exists(&self.prefixed(key))? {
+                return Err(RedisError::Str("KEEPTTL requires the key to exist"));
+            }
+        }
+
+        let meta_key = self.prefixed_meta(key);
+        self.expect_type(&meta_key, "hash")?;
+        let meta = self.hgetall(&meta_key)?;
+        for (idx, idx_val) in index_pairs(&meta) {
+            self.expect_type(&self.prefixed_idx(&idx, idx_val), "set")?;
+        }
+        for (kind, idx, _) in sorted_index_pairs(&meta) {
+            self.expect_type(&self.prefixed_sorted_idx(kind, idx), "zset")?;
+        }
+
+        let pairs = self.line_index_pairs(kv_index_line);
+        for (idx, idx_val) in &pairs {
+            match self.sorted_kind(idx) {
+                Some(kind) => {
+                    if pairs.iter().filter(|(name, _)| name == idx).count() > 1 {
+                        return Err(RedisError::String(format!(
+                            "ERR sorted index [ {} ] takes a single value!",
+                            String::from_utf8_lossy(idx)
+                        )));
+                    }
+                    kind.validate_value(idx_val)?;
+                    self.expect_type(&self.prefixed_sorted_idx(kind, idx), "zset")?;
+                }
+                None => self.expect_type(&self.prefixed_idx(idx, idx_val), "set")?,
+            }
+        }
+        Ok(())
     }
 
-    fn process_kv_line(&self, kv_index_line: &[RedisString]) -> RedisResult {
+    /// Compares the expected versions of all the lines before anything gets written, taking into account
+    /// the versions the preceding lines of the batch are going to write (each line draws the next one).
+    fn check_versions(&self) -> Result<(), RedisError> {
+        let mut versions = HashMap::new();
+        let mut last_version = self.last_version()?;
+        for (line, kv_index_line) in self.kv_index_lines.iter().enumerate() {
+            let key = kv_index_line[0].as_slice();
+            let expected = kv_index_line[self.version_field()]
+                .parse_integer()
+                .map_err(|_| line_error(line, kv_index_line, RedisError::Str("ERR invalid version!")))?;
+            let current = match versions.get(key) {
+                Some(version) => *version,
+                None => self.version(key)?,
+            };
+            if expected != current {
+                let mismatch = format!("{} expected version {}, found {}", VERSION_MISMATCH, expected, current);
+                return Err(line_error(line, kv_index_line, RedisError::String(mismatch)));
+            }
+            last_version += 1;
+            versions.insert(key, last_version);
+        }
+        Ok(())
+    }
+
+    /// Returns whether the line got written, i.e. its condition held.
+    fn process_kv_line(&self, kv_index_line: &[RedisString]) -> Result<bool, RedisError> {
         let key = &kv_index_line[0];
         let value = &kv_index_line[1];
-        let index_values = &kv_index_line[2..];
+        let expiry = self.expiry.of_line(kv_index_line)?;
 
-        // in case old value is present we need to make sure old index values are cleared
-        if self.exists(&self.prefixed_meta(key.borrow()))? {
-            self.clean_key(key.borrow())?;
+        // evaluated line by line, so that a key repeated within the batch sees the preceding lines written
+        let data_key = self.prefixed(key.as_slice());
+        let written = match self.condition_of_line(kv_index_line)? {
+            Condition::Always => true,
+            Condition::IfMissing => !self.exists(&data_key)?,
+            Condition::IfExists => self.exists(&data_key)?,
+        };
+        if !written {
+            return Ok(false);
         }
 
-        self.ctx
-            .call("SETEX", &[&self.prefixed(key.borrow()), self.expiry.borrow(), value.borrow()])?;
+        // in case old value is present we need to make sure old index values are cleared,
+        // the data key itself gets overwritten (keeping its TTL if asked to)
+        let version = self.next_version()?;
+        self.clean_meta(key.as_slice())?;
+
+        let set_options = expiry.set_options();
+        let mut set_args = vec![data_key.as_slice(), value.as_slice()];
+        set_args.extend(set_options.iter().map(Vec::as_slice));
+        self.call::<()>("SET", &set_args)?;
+
+        // an absolute deadline may have passed already, leaving nothing to index
+        if expiry.is_absolute() && !self.exists(&data_key)? {
+            return Ok(true);
+        }
+
+        let pairs = self.line_index_pairs(kv_index_line);
+        for (idx, idx_val) in &pairs {
+            match self.sorted_kind(idx) {
+                Some(kind) => self.add_to_sorted_index(key.as_slice(), kind, idx, idx_val)?,
+                None => self.add_to_index(key.as_slice(), idx, idx_val)?,
+            };
+        }
+
+        self.write_meta(key, &pairs, version)?;
+        Ok(true)
+    }
+
+    /// Position of the expected version on a line, following the other per-line fields.
+    fn version_field(&self) -> usize {
+        2 + self.expiry.line_fields() + self.condition.line_fields()
+    }
+
+    fn condition_of_line(&self, kv_index_line: &[RedisString]) -> Result<Condition, RedisError> {
+        match self.condition {
+            BatchCondition::Common(condition) => Ok(condition),
+            BatchCondition::PerKey => Condition::parse(kv_index_line[2 + self.expiry.line_fields()].as_slice())
+                .ok_or(RedisError::Str("ERR condition must be one of NX, XX, ANY!")),
+        }
+    }
 
-        for (idx, idx_val) in self.indices.iter().zip(index_values) {
-            self.add_to_index(key.borrow(), idx.borrow(), idx_val.borrow())?;
+    fn line_index_pairs<'l>(&'l self, kv_index_line: &'l [RedisString]) -> Vec<(&'l [u8], &'l [u8])> {
+        let index_args = kv_index_line[self.version_field() + self.cas as usize..]
+            .iter()
+            .map(RedisString::as_slice);
+        match &self.indices {
+            Indices::Common(indices) => indices.iter().map(RedisString::as_slice).zip(index_args).collect(),
+            Indices::PerLine => index_args.tuples().collect(),
         }
+    }
+
+    /// Index names written by the batch, each along with the kind the batch declares it as.
+    fn index_kinds(&self) -> Vec<(&[u8], IndexKind)> {
+        let names = match &self.indices {
+            Indices::Common(indices) => indices.iter().map(RedisString::as_slice).collect_vec(),
+            Indices::PerLine => self
+                .kv_index_lines
+                .iter()
+                .flat_map(|kv_index_line| self.line_index_pairs(kv_index_line))
+                .map(|(idx, _)| idx)
+                .unique()
+                .collect_vec(),
+        };
+        names.into_iter().map(|idx| (idx, self.sorted_kind(idx).into())).collect()
+    }
 
-        self.write_meta(key, index_values)
+    fn sorted_kind(&self, idx: &[u8]) -> Option<SortedIndex> {
+        self.sorted_indices
+            .iter()
+            .find(|(name, _)| name.as_slice() == idx)
+            .map(|(_, kind)| *kind)
     }
 
-    fn add_to_index(&self, key: &str, idx: &str, idx_val: &str) -> RedisResult {
-        self.ctx.call("SADD", &[&self.prefixed_idx(idx, idx_val), key])
+    fn add_to_index(&self, key: &[u8], idx: &[u8], idx_val: &[u8]) -> RedisResult {
+        self.call("SADD", &[&self.prefixed_idx(idx, idx_val), key])
     }
 
-    fn write_meta(&self, key: &RedisString, index_values: &[RedisString]) -> RedisResult {
-        let mut interleaved: Vec<&str> = interleave(&self.indices, index_values).map(Borrow::borrow).collect_vec();
-        if !interleaved.is_empty() {
-            let meta = self.prefixed_meta(key.borrow());
-            interleaved.insert(0, &meta);
-            self.ctx.call("HMSET", interleaved.as_slice())?;
+    /// Index names given more than once on the line (tags) keep each of their values in a field of its own,
+    /// sorted indices are told apart by the field prefix of their kind.
+    fn write_meta(&self, key: &RedisString, index_pairs: &[(&[u8], &[u8])], version: i64) -> RedisResult {
+        let meta = self.prefixed_meta(key.as_slice());
+        let version = version.to_string();
+        let name_counts = index_pairs.iter().map(|(idx, _)| *idx).counts();
+        let fields = index_pairs
+            .iter()
+            .map(|(idx, idx_val)| {
+                if let Some(kind) = self.sorted_kind(idx) {
+                    kind.field(idx)
+                } else if name_counts[idx] > 1 {
+                    tag_field(idx, idx_val)
+                } else {
+                    idx.to_vec()
+                }
+            })
+            .collect_vec();
+        let mut args = vec![meta.as_slice(), VERSION_FIELD, version.as_bytes()];
+        for (field, (_, idx_val)) in fields.iter().zip(index_pairs) {
+            args.push(field.as_slice());
+            args.push(*idx_val);
         }
+        self.call::<()>("HMSET", &args)?;
         REDIS_OK
     }
 }
 
+// <key> <value> [per-line fields] <num_pairs> <idx_1> <idx_1_val> ... <idx_n> <idx_n_val>
+// with the number of pairs left out of the parsed lines
+fn parse_lines_with_pairs(args: &mut impl Iterator<Item = RedisString>, line_fields: usize) -> Result<Vec<Vec<RedisString>>, RedisError> {
+    let mut kv_index_lines = vec![];
+    while let Some(key) = args.next() {
+        let mut kv_index_line = vec![key];
+        kv_index_line.extend(args.by_ref().take(line_fields - 1));
+        if kv_index_line.len() < line_fields {
+            return Err(RedisError::WrongArity);
+        }
+
+        let pair_count = args.next_u64()? as usize;
+        kv_index_line.extend(args.by_ref().take(2 * pair_count));
+        if kv_index_line.len() < line_fields + 2 * pair_count {
+            return Err(RedisError::WrongArity);
+        }
+
+        let names = kv_index_line[line_fields..].iter().step_by(2).map(RedisString::as_slice);
+        validate_index_names(names).map_err(|e| line_error(kv_index_lines.len(), &kv_index_line, e))?;
+        kv_index_lines.push(kv_index_line);
+    }
+    Ok(kv_index_lines)
+}
+
+// [NUMERIC <idx>] ...
+fn parse_sorted_indices(args: &mut Peekable<impl Iterator<Item = RedisString>>) -> Result<Vec<(RedisString, SortedIndex)>, RedisError> {
+    let mut sorted_indices: Vec<(RedisString, SortedIndex)> = vec![];
+    while let Some(kind) = args.peek().and_then(|arg| SortedIndex::parse(arg.as_slice())) {
+        args.next();
+        let idx = args.next_arg()?;
+        if sorted_indices.iter().any(|(name, _)| name.as_slice() == idx.as_slice()) {
+            return Err(RedisError::Str("ERR index names must be unique!"));
+        }
+        validate_index_names(std::iter::once(idx.as_slice()))?;
+        sorted_indices.push((idx, kind));
+    }
+    Ok(sorted_indices)
+}
+
+fn validate_index_names<'n>(mut names: impl Iterator<Item = &'n [u8]>) -> Result<(), RedisError> {
+    if names.any(|idx| idx.starts_with(RESERVED_PREFIX)) {
+        return Err(RedisError::Str("ERR index names must not start with '__'!"));
+    }
+    Ok(())
+}
+
+/// Expiry of the batch, either common to all the entries or given on each line right after the value.
+enum BatchExpiry {
+    Common(Expiry),
+    PerKey(TimeUnit),
+}
+
+impl BatchExpiry {
+    // <seconds> | EX <seconds> | PX <milliseconds> | EXAT <unix_time_seconds> | PXAT <unix_time_milliseconds> | KEEPTTL
+    //   | PERKEY [EX | PX | EXAT | PXAT]
+    fn parse<I: Iterator<Item = RedisString>>(args: &mut Peekable<I>) -> Result<Self, RedisError> {
+        let arg = args.next_arg()?;
+        let expiry = match arg.as_slice().to_ascii_uppercase().as_slice() {
+            b"KEEPTTL" => BatchExpiry::Common(Expiry::KeepTtl),
+            b"PERKEY" => match args.peek().and_then(|arg| TimeUnit::parse(arg.as_slice())) {
+                Some(unit) => {
+                    args.next();
+                    BatchExpiry::PerKey(unit)
+                }
+                None => BatchExpiry::PerKey(TimeUnit::Seconds),
+            },
+            option => match TimeUnit::parse(option) {
+                Some(unit) => BatchExpiry::Common(Expiry::Timed(unit, unit.expire_time(args.next_i64())?)),
+                None => BatchExpiry::Common(Expiry::Timed(
+                    TimeUnit::Seconds,
+                    TimeUnit::Seconds.expire_time(arg.parse_integer())?,
+                )),
+            },
+        };
+        Ok(expiry)
+    }
+
+    /// Number of arguments the expiry takes on each line.
+    fn line_fields(&self) -> usize {
+        match self {
+            BatchExpiry::Common(_) => 0,
+            BatchExpiry::PerKey(_) => 1,
+        }
+    }
+
+    fn of_line(&self, kv_index_line: &[RedisString]) -> Result<Expiry, RedisError> {
+        match self {
+            BatchExpiry::Common(expiry) => Ok(*expiry),
+            BatchExpiry::PerKey(unit) => Ok(Expiry::Timed(*unit, unit.expire_time(kv_index_line[2].parse_integer())?)),
+        }
+    }
+}
+
+/// How an entry expires, mapped onto the options of `SET`.
+#[derive(Clone, Copy)]
+enum Expiry {
+    Timed(TimeUnit, i64),
+    /// The entry keeps the TTL of its previous value.
+    KeepTtl,
+    Persist,
+}
+
+impl Expiry {
+    fn set_options(&self) -> Vec<Vec<u8>> {
+        match self {
+            Expiry::Timed(unit, n) => vec![unit.set_option().to_vec(), n.to_string().into_bytes()],
+            Expiry::KeepTtl => vec![b"KEEPTTL".to_vec()],
+            Expiry::Persist => vec![],
+        }
+    }
+
+    fn is_absolute(&self) -> bool {
+        matches!(
+            self,
+            Expiry::Timed(TimeUnit::UnixTimeSeconds, _) | Expiry::Timed(TimeUnit::UnixTimeMilliseconds, _)
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TimeUnit {
+    Seconds,
+    Milliseconds,
+    UnixTimeSeconds,
+    UnixTimeMilliseconds,
+}
+
+impl TimeUnit {
+    fn parse(option: &[u8]) -> Option<Self> {
+        match option.to_ascii_uppercase().as_slice() {
+            b"EX" => Some(TimeUnit::Seconds),
+            b"PX" => Some(TimeUnit::Milliseconds),
+            b"EXAT" => Some(TimeUnit::UnixTimeSeconds),
+            b"PXAT" => Some(TimeUnit::UnixTimeMilliseconds),
+            _ => None,
+        }
+    }
+
+    fn set_option(self) -> &'static [u8] {
+        match self {
+            TimeUnit::Seconds => b"EX",
+            TimeUnit::Milliseconds => b"PX",
+            TimeUnit::UnixTimeSeconds => b"EXAT",
+            TimeUnit::UnixTimeMilliseconds => b"PXAT",
+        }
+    }
+
+    /// Checks the expiry the way `SET` does (`getExpireMillisecondsOrReply`), so that no line of a batch gets
+    /// written before one `SET` would refuse: positive, in range once in milliseconds and, if relative, once added
+    /// to the current time.
+    fn expire_time(self, n: Result<i64, RedisError>) -> Result<i64, RedisError> {
+        let invalid = || RedisError::Str("ERR invalid expire time!");
+        let n = n.ok().filter(|n| *n > 0).ok_or_else(invalid)?;
+        let millis = match self {
+            TimeUnit::Seconds | TimeUnit::UnixTimeSeconds => n.checked_mul(1000),
+            TimeUnit::Milliseconds | TimeUnit::UnixTimeMilliseconds => Some(n),
+        };
+        let deadline = match self {
+            TimeUnit::Seconds | TimeUnit::Milliseconds => millis.and_then(|millis| millis.checked_add(binary::milliseconds())),
+            TimeUnit::UnixTimeSeconds | TimeUnit::UnixTimeMilliseconds => millis,
+        };
+        deadline.map(|_| n).ok_or_else(invalid)
+    }
+}
+
+/// Condition of the batch, either common to all the lines or given on each line
+/// (right after the value or the expiry of the line, if any).
+enum BatchCondition {
+    Common(Condition),
+    PerKey,
+}
+
+impl BatchCondition {
+    // [NX | XX | CONDITIONAL]
+    fn parse<I: Iterator<Item = RedisString>>(args: &mut Peekable<I>) -> Self {
+        let condition = match args.peek().map(|arg| arg.as_slice().to_ascii_uppercase()).as_deref() {
+            Some(b"NX") => BatchCondition::Common(Condition::IfMissing),
+            Some(b"XX") => BatchCondition::Common(Condition::IfExists),
+            Some(b"CONDITIONAL") => BatchCondition::PerKey,
+            _ => return BatchCondition::Common(Condition::Always),
+        };
+        args.next();
+        condition
+    }
+
+    /// Number of arguments the condition takes on each line.
+    fn line_fields(&self) -> usize {
+        match self {
+            BatchCondition::Common(_) => 0,
+            BatchCondition::PerKey => 1,
+        }
+    }
+}
+
+/// Condition of writing a line, the same as the `NX` / `XX` options of `SET`.
+#[derive(Clone, Copy, PartialEq)]
+enum Condition {
+    Always,
+    IfMissing,
+    IfExists,
+}
+
+impl Condition {
+    fn parse(option: &[u8]) -> Option<Self> {
+        match option.to_ascii_uppercase().as_slice() {
+            b"ANY" => Some(Condition::Always),
+            b"NX" => Some(Condition::IfMissing),
+            b"XX" => Some(Condition::IfExists),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps the error code of `VERSIONMISMATCH` errors, so that clients can tell them from the other (`ERR`) ones.
+fn line_error(line: usize, kv_index_line: &[RedisString], e: RedisError) -> RedisError {
+    let message = e.to_string();
+    let (code, message) = match message.split_once(' ') {
+        Some((code, rest)) if code == "ERR" || code == VERSION_MISMATCH => (code, rest),
+        _ => ("ERR", message.as_str()),
+    };
+    RedisError::String(format!(
+        "{} line {} (key [ {} ]): {}",
+        code,
+        line + 1,
+        String::from_utf8_lossy(kv_index_line[0].as_slice()),
+        message
+    ))
+}
+
 impl Namespaced for Set<'_> {
-    fn namespace(&self) -> &str {
-        self.namespace.borrow()
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
     }
 }
 
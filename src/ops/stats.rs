@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use super::*;
+
+/// Statistics of a single namespace: its size, computed by scanning the keyspace, along with
+/// the counters of its activity since the module got loaded.
+///
+/// The scans and the `MEMORY USAGE` of every key of the namespace make it meant for maintenance
+/// rather than regular use, the same as `Fsck`.
+pub struct Stats<'a> {
+    ctx: &'a Context,
+    namespace: RedisString,
+}
+
+impl<'a> Stats<'a> {
+    pub fn from(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        let mut args = args.into_iter().skip(1);
+
+        let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
+        args.done()?;
+
+        Ok(Stats { ctx, namespace })
+    }
+
+    pub fn process(&self) -> RedisResult {
+        let tag_pattern = glob_escape(&self.hash_tag());
+        let data_keys = self.scan_all(&[tag_pattern.as_slice(), b":*"].concat())?;
+        let meta_keys = self.scan_all(&[META_PREFIX, &tag_pattern, b":*"].concat())?;
+        let idx_keys = self.scan_all(&[INDEX_PREFIX, &tag_pattern, b":*"].concat())?;
+        let mut sorted_idx_keys = vec![];
+        for kind in SortedIndex::ALL {
+            sorted_idx_keys.extend(self.scan_all(&[kind.key_prefix(), &tag_pattern, b":*"].concat())?);
+        }
+
+        // number of distinct values of each index, sorted indices count the entries having a value instead
+        let mut indices = BTreeMap::new();
+        for idx_key in &idx_keys {
+            if let Some(ModuleKey::Index { idx, .. }) = parse_key(idx_key) {
+                *indices.entry(idx.into_owned()).or_insert(0) += 1;
+            }
+        }
+        for idx_key in &sorted_idx_keys {
+            if let Some(ModuleKey::SortedIndex { idx, .. }) = parse_key(idx_key) {
+                let entries: i64 = self.call("ZCARD", &[idx_key])?;
+                *indices.entry(idx.to_vec()).or_insert(0) += entries;
+            }
+        }
+
+        let namespace_keys = [self.prefixed_version_counter(), self.prefixed_index_kinds()];
+        let mut memory = 0;
+        for key in data_keys
+            .iter()
+            .chain(&meta_keys)
+            .chain(&idx_keys)
+            .chain(&sorted_idx_keys)
+            .chain(&namespace_keys)
+        {
+            memory += self.memory_usage(key)?;
+        }
+
+        let stats = metrics::namespace_stats(self.namespace());
+        Ok(RedisValue::Array(vec![
+            RedisValue::BulkString("keys".to_string()),
+            RedisValue::Integer(data_keys.len() as i64),
+            RedisValue::BulkString("indices".to_string()),
+            RedisValue::Array(
+                indices
+                    .into_iter()
+                    .flat_map(|(idx, values)| vec![RedisValue::StringBuffer(idx), RedisValue::Integer(values)])
+                    .collect(),
+            ),
+            RedisValue::BulkString("expirations".to_string()),
+            RedisValue::Integer(stats.expirations),
+            RedisValue::BulkString("evictions".to_string()),
+            RedisValue::Integer(stats.evictions),
+            RedisValue::BulkString("removals".to_string()),
+            RedisValue::Integer(stats.removals),
+            RedisValue::BulkString("hits".to_string()),
+            RedisValue::Integer(stats.hits),
+            RedisValue::BulkString("misses".to_string()),
+            RedisValue::Integer(stats.misses),
+            RedisValue::BulkString("memory_bytes".to_string()),
+            RedisValue::Integer(memory),
+        ]))
+    }
+
+    /// Approximate memory of the key, which is gone (nil) in case it expired in the middle of the scan
+    /// (or never existed).
+    fn memory_usage(&self, key: &[u8]) -> Result<i64, RedisError> {
+        match self.call::<RedisValue>("MEMORY", &[b"USAGE", key])? {
+            RedisValue::Integer(bytes) => Ok(bytes),
+            _ => Ok(0),
+        }
+    }
+}
+
+impl Namespaced for Stats<'_> {
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
+    }
+}
+
+impl Contextual for Stats<'_> {
+    fn context(&self) -> &Context {
+        self.ctx
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use super::*;
+
+/// `SET` of an index name given more than once makes it a tag, holding all the values.
+enum IndexUpdate {
+    Set(RedisString, Vec<RedisString>),
+    Del(RedisString),
+}
+
+impl IndexUpdate {
+    fn idx(&self) -> &[u8] {
+        match self {
+            IndexUpdate::Set(idx, _) | IndexUpdate::Del(idx) => idx.as_slice(),
+        }
+    }
+}
+
+/// Changes, adds or removes index values of an existing entry, keeping its value and TTL.
+///
+/// A sorted index (numeric, lex or geo) stays sorted when its value changes.
+///
+/// Given the version the entry is expected to have, the update fails with `VERSIONMISMATCH` when the entry has
+/// another one, the same as a `CAS` write (`0` standing for no entry).
+pub struct UpdateIndices<'a> {
+    ctx: &'a Context,
+    namespace: RedisString,
+    key: RedisString,
+    expected_version: Option<i64>,
+    updates: Vec<IndexUpdate>,
+}
+
+impl<'a> UpdateIndices<'a> {
+    pub fn from(ctx: &'a Context, args: Vec<RedisString>) -> Result<Self, RedisError> {
+        let mut args = args.into_iter().skip(1);
+
+        let namespace = args.next_arg()?;
+        validate_namespace(namespace.as_slice())?;
+        let key = args.next_arg()?;
+
+        let mut expected_version = None;
+        let mut updates = vec![];
+        while let Some(arg) = args.next() {
+            match arg.as_slice().to_ascii_uppercase().as_slice() {
+                b"VERSION" if expected_version.is_none() => {
+                    let version = args.next_i64().map_err(|_| RedisError::Str("ERR invalid version!"))?;
+                    expected_version = Some(version);
+                }
+                b"SET" => {
+                    let (idx, idx_val) = (args.next_arg()?, args.next_arg()?);
+                    let tag = updates.iter_mut().find_map(|update| match update {
+                        IndexUpdate::Set(name, values) if name.as_slice() == idx.as_slice() => Some(values),
+                        _ => None,
+                    });
+                    match tag {
+                        Some(values) => values.push(idx_val),
+                        None => updates.push(IndexUpdate::Set(idx, vec![idx_val])),
+                    }
+                }
+                b"DEL" => updates.push(IndexUpdate::Del(args.next_arg()?)),
+                _ => return Err(RedisError::Str("ERR syntax error")),
+            }
+        }
+        if updates.is_empty() {
+            return Err(RedisError::WrongArity);
+        }
+        if updates.iter().map(IndexUpdate::idx).unique().count() != updates.len() {
+            return Err(RedisError::Str("ERR index names must be unique!"));
+        }
+        if updates.iter().any(|update| update.idx().starts_with(RESERVED_PREFIX)) {
+            return Err(RedisError::Str("ERR index names must not start with '__'!"));
+        }
+
+        Ok(UpdateIndices {
+            ctx,
+            namespace,
+            key,
+            expected_version,
+            updates,
+        })
+    }
+
+    /// Replies `1` once the indices got updated, `0` when there is no such entry.
+    pub fn process(&self) -> RedisResult {
+        let key = self.key.as_slice();
+        if let Some(expected) = self.expected_version {
+            let current = self.version(key)?;
+            if expected != current {
+                let mismatch = format!("{} expected version {}, found {}", VERSION_MISMATCH, expected, current);
+                return Err(RedisError::String(mismatch));
+            }
+        }
+        if !self.exists(&self.prefixed(key))? {
+            return Ok(RedisValue::Integer(0));
+        }
+
+        let meta_key = self.prefixed_meta(key);
+        self.expect_type(&meta_key, "hash")?;
+        let meta = self.hgetall(&meta_key)?;
+        // indices new to the entry take the kind recorded for the namespace
+        let mut kinds = HashMap::new();
+        for update in &self.updates {
+            if let IndexUpdate::Set(idx, _) = update {
+                let idx = idx.as_slice();
+                let kind = match sorted_index_pairs(&meta).find(|(_, name, _)| *name == idx) {
+                    Some((kind, _, _)) => IndexKind::Sorted(kind),
+                    None => self.index_kind(idx)?.unwrap_or(IndexKind::Exact),
+                };
+                kinds.insert(idx, kind);
+            }
+        }
+        let sorted_kind = |idx: &[u8]| kinds.get(idx).and_then(|kind| kind.sorted());
+        for update in &self.updates {
+            if let IndexUpdate::Set(idx, idx_vals) = update {
+                let idx = idx.as_slice();
+                for idx_val in idx_vals {
+                    match sorted_kind(idx) {
+                        Some(kind) => {
+                            if idx_vals.len() > 1 {
+                                return Err(RedisError::String(format!(
+                                    "ERR sorted index [ {} ] takes a single value!",
+                                    String::from_utf8_lossy(idx)
+                                )));
+                            }
+                            kind.validate_value(idx_val.as_slice())?;
+                            self.expect_type(&self.prefixed_sorted_idx(kind, idx), "zset")?;
+                        }
+                        None => self.expect_type(&self.prefixed_idx(idx, idx_val.as_slice()), "set")?,
+                    }
+                }
+            }
+        }
+
+        for (idx, kind) in &kinds {
+            self.record_index_kind(idx, *kind)?;
+        }
+        let version = self.next_version()?;
+        for update in &self.updates {
+            let idx = update.idx();
+            for (_, old_val) in index_pairs(&meta).filter(|(name, _)| name.as_ref() == idx) {
+                self.rm_from_index(key, idx, old_val)?;
+                self.call::<()>("HDEL", &[&meta_key, idx, &tag_field(idx, old_val)])?;
+            }
+            for (kind, _, old_val) in sorted_index_pairs(&meta).filter(|(_, name, _)| *name == idx) {
+                self.rm_from_sorted_index(key, kind, idx, old_val)?;
+                self.call::<()>("HDEL", &[&meta_key, &kind.field(idx)])?;
+            }
+            match (update, sorted_kind(idx)) {
+                (IndexUpdate::Set(_, idx_vals), Some(kind)) => {
+                    self.add_to_sorted_index(key, kind, idx, idx_vals[0].as_slice())?;
+                    self.call::<()>("HSET", &[&meta_key, &kind.field(idx), idx_vals[0].as_slice()])?;
+                }
+                (IndexUpdate::Set(_, idx_vals), None) => {
+                    for idx_val in idx_vals {
+                        let field = if idx_vals.len() > 1 {
+                            tag_field(idx, idx_val.as_slice())
+                        } else {
+                            idx.to_vec()
+                        };
+                        self.call::<()>("SADD", &[&self.prefixed_idx(idx, idx_val.as_slice()), key])?;
+                        self.call::<()>("HSET", &[&meta_key, &field, idx_val.as_slice()])?;
+                    }
+                }
+                (IndexUpdate::Del(_), _) => {}
+            }
+        }
+        self.call::<()>("HSET", &[&meta_key, VERSION_FIELD, version.to_string().as_bytes()])?;
+
+        Ok(RedisValue::Integer(1))
+    }
+}
+
+impl Namespaced for UpdateIndices<'_> {
+    fn namespace(&self) -> &[u8] {
+        self.namespace.as_slice()
+    }
+}
+
+impl Contextual for UpdateIndices<'_> {
+    fn context(&self) -> &Context {
+        self.ctx
+    }
+}
+
+impl CleanOperation for UpdateIndices<'_> {}
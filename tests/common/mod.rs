@@ -22,17 +22,27 @@ struct ChildRedis {
 }
 
 impl ChildRedis {
-    fn spawn(name: &str) -> RedisResult<ChildRedis> {
+    fn spawn(name: &str, cluster: bool) -> RedisResult<ChildRedis> {
         let port = TEST_REDIS_PORT_NUMBER.fetch_add(1, Ordering::SeqCst);
         let log_file = ChildRedis::get_log_file(name)?;
-        let child = Command::new(REDIS_BIN.unwrap_or("/usr/bin/redis-server"))
+        let mut command = Command::new(REDIS_BIN.unwrap_or("/usr/bin/redis-server"));
+        command
             .arg("--port")
             .arg(port.to_string())
             .arg("--logfile")
             .arg(&log_file)
             .arg("--maxmemory-policy")
-            .arg("volatile-lru")
-            .spawn()?;
+            .arg("volatile-lru");
+        if cluster {
+            let nodes_file = format!("test_outputs/{}-nodes.conf", name);
+            let _ = std::fs::remove_file(&nodes_file);
+            command
+                .arg("--cluster-enabled")
+                .arg("yes")
+                .arg("--cluster-config-file")
+                .arg(nodes_file);
+        }
+        let child = command.spawn()?;
 
         let log_tail = Command::new("/usr/bin/tail")
             .arg("-F")
@@ -71,33 +81,86 @@ impl Drop for ChildRedis {
     }
 }
 
-pub(crate) fn with_redis_conn<F>(name: &str, f: F) -> RedisResult<()>
+pub(crate) fn with_redis_conn<F>(name: &str, cluster: bool, f: F) -> RedisResult<()>
 where
     F: FnOnce(Connection) -> RedisResult<()>,
 {
-    let redis = ChildRedis::spawn(name)?;
-    let conn = get_conn(redis.port)?;
+    let redis = ChildRedis::spawn(name, cluster)?;
+    let mut conn = get_conn(redis.port)?;
+    if cluster {
+        own_all_slots(&mut conn)?;
+    }
     f(conn)
 }
 
+/// Runs `f` with connections to a primary and a replica of it, the module loaded by both of them.
+pub(crate) fn with_replicated_redis_conns<F>(name: &str, f: F) -> RedisResult<()>
+where
+    F: FnOnce(Connection, Connection) -> RedisResult<()>,
+{
+    let primary = ChildRedis::spawn(&format!("{}-primary", name), false)?;
+    let replica = ChildRedis::spawn(&format!("{}-replica", name), false)?;
+    let mut primary_conn = get_conn(primary.port)?;
+    let mut replica_conn = get_conn(replica.port)?;
+    load_module(&mut primary_conn)?;
+    load_module(&mut replica_conn)?;
+
+    redis::cmd("REPLICAOF")
+        .arg("127.0.0.1")
+        .arg(primary.port)
+        .query(&mut replica_conn)?;
+    for _ in 0..100 {
+        let info: String = redis::cmd("INFO").arg("replication").query(&mut replica_conn)?;
+        if info.contains("master_link_status:up") {
+            return f(primary_conn, replica_conn);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    Err(Error::new(ErrorKind::Other, "Replica failed to connect to the primary").into())
+}
+
+/// Makes the node a single-node cluster serving all the slots.
+fn own_all_slots(conn: &mut Connection) -> RedisResult<()> {
+    redis::cmd("CLUSTER")
+        .arg("ADDSLOTS")
+        .arg((0..16384).collect::<Vec<u16>>())
+        .query(conn)?;
+    for _ in 0..100 {
+        let info: String = redis::cmd("CLUSTER").arg("INFO").query(conn)?;
+        if info.contains("cluster_state:ok") {
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    Err(Error::new(ErrorKind::Other, "Cluster failed to get ready").into())
+}
+
 pub(crate) fn get_conn(port: usize) -> RedisResult<Connection> {
     let client = redis::Client::open(format!("redis://127.0.0.1:{port}/", port = port))?;
     client.get_connection()
 }
 
 pub(crate) fn load_module(conn: &mut Connection) -> RedisResult<()> {
-    redis::cmd("MODULE").arg("LOAD").arg(get_module_path()).query(conn)
+    load_module_with_args(conn, &[])
 }
 
-const METRICS_KEY_COUNT: usize = 3;
+pub(crate) fn load_module_with_args(conn: &mut Connection, args: &[&str]) -> RedisResult<()> {
+    redis::cmd("MODULE").arg("LOAD").arg(get_module_path()).arg(args).query(conn)
+}
 
 pub fn assert_keys_count(conn: &mut Connection, count: usize) -> RedisResult<()> {
     let keys: Vec<String> = conn.keys("*")?;
-    assert_eq!(keys.len(), METRICS_KEY_COUNT + count);
+    assert_eq!(keys.len(), count);
 
     Ok(())
 }
 
+/// Value of a counter reported by `INFO dragon_map`.
+pub fn get_metric(conn: &mut Connection, name: &str) -> RedisResult<i64> {
+    let info: redis::InfoDict = redis::cmd("INFO").arg("dragon_map").query(conn)?;
+    Ok(info.get(&format!("dragon_map_{}", name)).expect("metric not reported"))
+}
+
 pub fn assert_key_value(conn: &mut Connection, expected_value: &str, key: &str) -> RedisResult<()> {
     let value: String = conn.get(key)?;
     assert_eq!(expected_value, value);
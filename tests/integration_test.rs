@@ -2,7 +2,7 @@ extern crate redis;
 
 use std::collections::HashMap;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use itertools::Itertools;
 use redis::{Commands, Connection, RedisResult};
@@ -27,8 +27,9 @@ fn test_msetex_simple(mut conn: Connection) -> RedisResult<()> {
         .arg("v")
         .query(&mut conn)?;
 
-    assert_keys_count(&mut conn, 1)?;
-    assert_key_value(&mut conn, "v", "test_ns:k")
+    assert_keys_count(&mut conn, 3)?; // data, meta (holding the version) and the namespace's version counter
+    assert!(conn.exists("version_{test_ns}")?);
+    assert_key_value(&mut conn, "v", "{test_ns}:k")
 }
 
 #[redis_test(loaded_module)]
@@ -45,9 +46,10 @@ fn test_msetex_multival(mut conn: Connection) -> RedisResult<()> {
         .arg("v3")
         .query(&mut conn)?;
 
-    assert_keys_count(&mut conn, 3)?;
+    assert_keys_count(&mut conn, 7)?;
+    assert!(conn.exists("version_{test_ns}")?);
 
-    let values: Vec<String> = conn.get(vec!["test_ns:k1", "test_ns:k2", "test_ns:k3"])?;
+    let values: Vec<String> = conn.get(vec!["{test_ns}:k1", "{test_ns}:k2", "{test_ns}:k3"])?;
     assert_eq!(vec!["v1", "v2", "v3"], values);
 
     Ok(())
@@ -67,155 +69,2124 @@ fn test_msetex_expiry(mut conn: Connection) -> RedisResult<()> {
 
     sleep(Duration::from_secs(2));
 
-    let values: Vec<String> = conn.get(vec!["test_ns:k1", "test_ns:k2"])?;
+    let values: Vec<String> = conn.get(vec!["{test_ns}:k1", "{test_ns}:k2"])?;
     assert!(values.is_empty());
 
     Ok(())
 }
 
 #[redis_test(loaded_module)]
-fn test_msetex_indexed(mut conn: Connection) -> RedisResult<()> {
+fn test_msetex_expiry_options(mut conn: Connection) -> RedisResult<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
     redis::cmd("MAP.MSETEX_INDEXED")
         .arg("test_ns")
-        .arg(10)
-        .arg(2)
+        .arg("PX")
+        .arg(100_000)
+        .arg(1)
         .arg("first")
-        .arg("second")
         .arg("k1")
         .arg("v1")
         .arg("x")
-        .arg("y")
+        .query(&mut conn)?;
+    let pttl: i64 = conn.pttl("{test_ns}:k1")?;
+    assert!(pttl > 90_000 && pttl <= 100_000);
+
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg("EXAT")
+        .arg(now.as_secs() + 1000)
+        .arg(1)
+        .arg("first")
         .arg("k2")
         .arg("v2")
-        .arg("w")
+        .arg("x")
+        .query(&mut conn)?;
+    let ttl: i64 = conn.ttl("{test_ns}:k2")?;
+    assert!(ttl > 990 && ttl <= 1000);
+
+    // updates keep the TTL while the indices get replaced
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg("KEEPTTL")
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1'")
+        .arg("y")
+        .query(&mut conn)?;
+    let pttl: i64 = conn.pttl("{test_ns}:k1")?;
+    assert!(pttl > 90_000 && pttl <= 100_000);
+    assert_key_value(&mut conn, "v1'", "{test_ns}:k1")?;
+    assert_members(&mut conn, vec!["k2"], "idx_{test_ns}:first:x")?;
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:first:y")?;
+
+    let missing: RedisResult<()> = redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg("KEEPTTL")
+        .arg(0)
+        .arg("k3")
+        .arg("v3")
+        .query(&mut conn);
+    assert!(missing.is_err());
+
+    // deadline in the past
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg("PXAT")
+        .arg(now.as_millis() as u64 - 1000)
+        .arg(1)
+        .arg("first")
+        .arg("k4")
+        .arg("v4")
         .arg("z")
         .query(&mut conn)?;
+    assert!(!conn.exists("{test_ns}:k4")?);
+    assert!(!conn.exists("idx_{test_ns}:first:z")?);
 
-    let values: Vec<String> = conn.get(vec!["test_ns:k1", "test_ns:k2"])?;
-    assert_eq!(vec!["v1", "v2"], values);
+    let invalid: RedisResult<()> = redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg("PX")
+        .arg(0)
+        .arg(0)
+        .arg("k5")
+        .arg("v5")
+        .query(&mut conn);
+    assert!(invalid.is_err());
 
-    assert_members(&mut conn, vec!["k1"], "idx_test_ns:first:x")?;
-    assert_members(&mut conn, vec!["k2"], "idx_test_ns:first:w")?;
-    assert_members(&mut conn, vec!["k1"], "idx_test_ns:second:y")?;
-    assert_members(&mut conn, vec!["k2"], "idx_test_ns:second:z")?;
+    Ok(())
+}
 
-    let meta_k1: HashMap<String, String> = conn.hgetall("meta_test_ns:k1")?;
-    assert_eq!(vec!["first", "second"], meta_k1.keys().sorted().collect::<Vec<&String>>());
+#[redis_test(loaded_module)]
+fn test_msetex_per_key_expiry(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg("PERKEY")
+        .arg("PX")
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg(1000)
+        .arg("x")
+        .arg("k2")
+        .arg("v2")
+        .arg(100_000)
+        .arg("x")
+        .query(&mut conn)?;
 
-    assert_eq!("x", meta_k1.get("first").unwrap());
-    assert_eq!("y", meta_k1.get("second").unwrap());
+    let pttl: i64 = conn.pttl("{test_ns}:k2")?;
+    assert!(pttl > 90_000 && pttl <= 100_000);
 
-    let meta_k2: HashMap<String, String> = conn.hgetall("meta_test_ns:k2")?;
-    assert_eq!(vec!["first", "second"], meta_k2.keys().sorted().collect::<Vec<&String>>());
+    sleep(Duration::from_secs(2));
 
-    assert_eq!("w", meta_k2.get("first").unwrap());
-    assert_eq!("z", meta_k2.get("second").unwrap());
+    assert!(!conn.exists("{test_ns}:k1")?);
+    assert_members(&mut conn, vec!["k2"], "idx_{test_ns}:first:x")?;
+
+    let invalid: RedisResult<()> = redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg("PERKEY")
+        .arg(0)
+        .arg("k3")
+        .arg("v3")
+        .arg(10)
+        .arg("k4")
+        .arg("v4")
+        .arg("never")
+        .query(&mut conn);
+    assert!(invalid.is_err());
+    assert!(!conn.exists("{test_ns}:k3")?);
+
+    // in range of an i64, yet out of range once in milliseconds: SET would fail on the second line
+    let overflowing: RedisResult<()> = redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg("PERKEY")
+        .arg(1)
+        .arg("i")
+        .arg("k5")
+        .arg("v5")
+        .arg(10)
+        .arg("x")
+        .arg("k6")
+        .arg("v6")
+        .arg(i64::MAX / 1000 + 1)
+        .arg("y")
+        .query(&mut conn);
+    let err = overflowing.unwrap_err();
+    assert_eq!(Some("line 2 (key [ k6 ]): invalid expire time!"), err.detail());
+    assert!(!conn.exists("{test_ns}:k5")?);
+
+    // in range once in milliseconds, yet overflowing once added to the current time
+    let overflowing: RedisResult<()> = redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg("PX")
+        .arg(i64::MAX - 1000)
+        .arg(0)
+        .arg("k7")
+        .arg("v7")
+        .query(&mut conn);
+    assert!(overflowing.is_err());
+    assert!(!conn.exists("{test_ns}:k7")?);
 
     Ok(())
 }
 
 #[redis_test(loaded_module)]
-fn test_msetex_indexed_update(mut conn: Connection) -> RedisResult<()> {
+fn test_mset_indexed_persists(mut conn: Connection) -> RedisResult<()> {
     redis::cmd("MAP.MSETEX_INDEXED")
         .arg("test_ns")
         .arg(10)
-        .arg(2)
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .query(&mut conn)?;
+    redis::cmd("MAP.MSET_INDEXED")
+        .arg("test_ns")
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1'")
+        .arg("x")
+        .arg("k2")
+        .arg("v2")
+        .arg("x")
+        .query(&mut conn)?;
+
+    // overwriting drops the previous expiry
+    let ttl: i64 = conn.ttl("{test_ns}:k1")?;
+    assert_eq!(-1, ttl);
+    let ttl: i64 = conn.ttl("{test_ns}:k2")?;
+    assert_eq!(-1, ttl);
+    let members: Vec<String> = conn.smembers("idx_{test_ns}:first:x")?;
+    assert_eq!(vec!["k1", "k2"], members.into_iter().sorted().collect_vec());
+
+    let removed: i64 = redis::cmd("MAP.REM_BY_INDEX")
+        .arg("test_ns")
         .arg("first")
-        .arg("second")
-        .arg("k")
-        .arg("v")
         .arg("x")
-        .arg("y")
         .query(&mut conn)?;
+    assert_eq!(2, removed);
+    // the version counter and index kinds of the namespace outlive its entries
+    assert!(conn.exists("version_{test_ns}")?);
+    assert!(conn.exists("indices_{test_ns}")?);
+    assert_keys_count(&mut conn, 2)
+}
 
+#[redis_test(loaded_module)]
+fn test_msetex_conditional(mut conn: Connection) -> RedisResult<()> {
     redis::cmd("MAP.MSETEX_INDEXED")
         .arg("test_ns")
         .arg(10)
-        .arg(2)
-        .arg("second")
-        .arg("third")
-        .arg("k")
-        .arg("v")
-        .arg("y2")
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .query(&mut conn)?;
+
+    let written: Vec<i64> = redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg("NX")
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1'")
+        .arg("y")
+        .arg("k2")
+        .arg("v2")
+        .arg("y")
+        .query(&mut conn)?;
+    assert_eq!(vec![0, 1], written);
+    assert_key_value(&mut conn, "v1", "{test_ns}:k1")?;
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:first:x")?;
+    assert_members(&mut conn, vec!["k2"], "idx_{test_ns}:first:y")?;
+
+    let written: Vec<i64> = redis::cmd("MAP.MSET_INDEXED")
+        .arg("test_ns")
+        .arg("XX")
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1'")
+        .arg("z")
+        .arg("k3")
+        .arg("v3")
         .arg("z")
         .query(&mut conn)?;
+    assert_eq!(vec![1, 0], written);
+    assert_key_value(&mut conn, "v1'", "{test_ns}:k1")?;
+    assert!(!conn.exists("{test_ns}:k3")?);
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:first:z")?;
 
-    assert_key_value(&mut conn, "v", "test_ns:k")?;
+    let written: Vec<i64> = redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg("CONDITIONAL")
+        .arg(0)
+        .arg("k2")
+        .arg("v2'")
+        .arg("NX")
+        .arg("k3")
+        .arg("v3")
+        .arg("NX")
+        .arg("k3")
+        .arg("v3'")
+        .arg("XX")
+        .arg("k4")
+        .arg("v4")
+        .arg("ANY")
+        .query(&mut conn)?;
+    assert_eq!(vec![0, 1, 1, 1], written);
+    assert_key_value(&mut conn, "v2", "{test_ns}:k2")?;
+    assert_key_value(&mut conn, "v3'", "{test_ns}:k3")?;
+    assert_key_value(&mut conn, "v4", "{test_ns}:k4")?;
 
-    assert!(!conn.exists("idx_test_ns:first:x")?);
-    assert!(!conn.exists("idx_test_ns:second:y")?);
-    assert_members(&mut conn, vec!["k"], "idx_test_ns:second:y2")?;
-    assert_members(&mut conn, vec!["k"], "idx_test_ns:third:z")
+    Ok(())
 }
 
 #[redis_test(loaded_module)]
-fn test_msetex_index_unique_validation(mut conn: Connection) -> RedisResult<()> {
-    match redis::cmd("MAP.MSETEX_INDEXED")
+fn test_msetex_versions(mut conn: Connection) -> RedisResult<()> {
+    for value in &["v1", "v2"] {
+        redis::cmd("MAP.MSETEX_INDEXED")
+            .arg("test_ns")
+            .arg(10)
+            .arg(1)
+            .arg("first")
+            .arg("k1")
+            .arg(value)
+            .arg("x")
+            .query(&mut conn)?;
+    }
+
+    let (value, version): (String, i64) = redis::cmd("MAP.GET").arg("test_ns").arg("k1").arg("WITHVERSION").query(&mut conn)?;
+    assert_eq!("v2", value);
+    assert_eq!(2, version);
+
+    // version 0 stands for an entry not present yet
+    redis::cmd("MAP.MSETEX_INDEXED")
         .arg("test_ns")
+        .arg(10)
+        .arg("CAS")
         .arg(1)
-        .arg(3)
-        .arg("duplicate")
-        .arg("duplicate")
-        .arg("third")
-        .arg("k")
-        .arg("v")
-        .arg("x")
+        .arg("first")
+        .arg("k1")
+        .arg("v3")
+        .arg(2)
         .arg("y")
-        .arg("z")
+        .arg("k2")
+        .arg("w1")
+        .arg(0)
+        .arg("y")
+        .query(&mut conn)?;
+    assert_key_value(&mut conn, "v3", "{test_ns}:k1")?;
+    assert_key_value(&mut conn, "w1", "{test_ns}:k2")?;
+
+    match redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg("CAS")
+        .arg(0)
+        .arg("k2")
+        .arg("w2")
+        .arg(4)
+        .arg("k1")
+        .arg("v4")
+        .arg(2)
         .query(&mut conn)
     {
-        Ok(()) => panic!("Expected validation failure"),
-        Err(err) => assert_eq!(Some("index names must be unique!"), err.detail()),
+        Ok(()) => panic!("Expected version mismatch"),
+        Err(err) => {
+            assert_eq!(Some("VERSIONMISMATCH"), err.code());
+            assert_eq!(Some("line 2 (key [ k1 ]): expected version 2, found 3"), err.detail());
+        }
     }
+    assert_key_value(&mut conn, "w1", "{test_ns}:k2")?;
+
+    let entries: Vec<(String, Vec<String>, i64)> = redis::cmd("MAP.GET_BY_INDEX")
+        .arg("test_ns")
+        .arg("first")
+        .arg("y")
+        .arg("WITHINDICES")
+        .arg("WITHVERSION")
+        .query(&mut conn)?;
+    let versions = entries
+        .into_iter()
+        .map(|(value, _, version)| (value, version))
+        .sorted()
+        .collect_vec();
+    assert_eq!(vec![("v3".to_string(), 3), ("w1".to_string(), 4)], versions);
 
     Ok(())
 }
 
 #[redis_test(loaded_module)]
-fn test_msetex_indexed_load(mut conn: Connection) -> RedisResult<()> {
-    for i in 1..10_000 {
+fn test_versions_survive_recreation(mut conn: Connection) -> RedisResult<()> {
+    let set = |conn: &mut Connection, value: &str, version: i64| -> RedisResult<()> {
         redis::cmd("MAP.MSETEX_INDEXED")
             .arg("test_ns")
-            .arg(1)
-            .arg(3)
-            .arg("first")
-            .arg("second")
-            .arg("third")
-            .arg(format!("k{}", i))
-            .arg(format!("v{}", i))
-            .arg(format!("{}", i / 2))
-            .arg(format!("{}", i / 3))
-            .arg(format!("{}", i / 4))
-            .query(&mut conn)?;
+            .arg(10)
+            .arg("CAS")
+            .arg(0)
+            .arg("k1")
+            .arg(value)
+            .arg(version)
+            .query(conn)
+    };
+    set(&mut conn, "v1", 0)?;
+    let (_, stale_version): (String, i64) = redis::cmd("MAP.GET").arg("test_ns").arg("k1").arg("WITHVERSION").query(&mut conn)?;
+
+    let removed: i64 = redis::cmd("MAP.MREM").arg("test_ns").arg("k1").query(&mut conn)?;
+    assert_eq!(1, removed);
+    set(&mut conn, "v2", 0)?;
+
+    // the re-created entry doesn't start over, so a writer holding the version of the removed one is turned down
+    match set(&mut conn, "v3", stale_version) {
+        Ok(()) => panic!("Expected version mismatch"),
+        Err(err) => assert_eq!(Some("VERSIONMISMATCH"), err.code()),
     }
+    assert_key_value(&mut conn, "v2", "{test_ns}:k1")?;
 
-    thread::sleep(Duration::from_secs(5));
+    let (_, version): (String, i64) = redis::cmd("MAP.GET").arg("test_ns").arg("k1").arg("WITHVERSION").query(&mut conn)?;
+    assert!(version > stale_version);
+    let last_version: i64 = conn.get("version_{test_ns}")?;
+    assert_eq!(version, last_version);
 
-    assert_keys_count(&mut conn, 0)
+    Ok(())
 }
 
 #[redis_test(loaded_module)]
-fn test_msetex_indexed_randomized(mut conn: Connection) -> RedisResult<()> {
-    let mut rng = rand::thread_rng();
+fn test_mset_indexed_evicted(mut conn: Connection) -> RedisResult<()> {
+    // the harness only evicts volatile keys, entries without expiry are evicted under allkeys-* policies
+    for (parameter, value) in [("maxmemory-policy", "allkeys-lru"), ("maxmemory-samples", "10")] {
+        let _: () = redis::cmd("CONFIG").arg("SET").arg(parameter).arg(value).query(&mut conn)?;
+    }
 
-    for i in 1..10_000 {
-        let idx_base = rng.gen_range(0, 1000);
-        redis::cmd("MAP.MSETEX_INDEXED")
-            .arg("test_ns")
-            .arg(1)
-            .arg(3)
-            .arg(idx_base)
-            .arg(idx_base + 1)
-            .arg(idx_base + 2)
-            .arg(format!("{}", rng.gen_range(0, 1000)))
-            .arg(format!("v{}", i))
-            .arg(rng.gen_range(0, 100))
-            .arg(rng.gen_range(0, 100))
-            .arg(rng.gen_range(0, 100))
-            .query(&mut conn)?;
+    let value = "v".repeat(10_000);
+    let mut cmd = redis::cmd("MAP.MSET_INDEXED");
+    cmd.arg("test_ns").arg(1).arg("first");
+    for i in 0..200 {
+        cmd.arg(format!("k{}", i)).arg(&value).arg(i % 2);
     }
+    cmd.query(&mut conn)?;
 
-    thread::sleep(Duration::from_secs(5));
+    // leave the data keys the least recently used ones (by more than the LRU clock resolution of a second),
+    // so that they get evicted rather than the keys indexing them
+    sleep(Duration::from_secs(2));
+    for i in 0..200 {
+        let _: usize = conn.hlen(format!("meta_{{test_ns}}:k{}", i))?;
+    }
+    for idx_val in 0..2 {
+        let _: usize = conn.scard(format!("idx_{{test_ns}}:first:{}", idx_val))?;
+    }
+    let _: i64 = conn.get("version_{test_ns}")?;
+    let _: usize = conn.hlen("indices_{test_ns}")?;
+
+    // room for about three quarters of the values
+    let used_memory = |conn: &mut Connection| -> RedisResult<i64> {
+        let info: redis::InfoDict = redis::cmd("INFO").arg("memory").query(conn)?;
+        Ok(info.get("used_memory").expect("used memory not reported"))
+    };
+    let maxmemory = used_memory(&mut conn)? - 500_000;
+    let _: () = redis::cmd("CONFIG").arg("SET").arg("maxmemory").arg(maxmemory).query(&mut conn)?;
+    // evictions carry on in the background once they take too long for a single command
+    for _ in 0..100 {
+        if used_memory(&mut conn)? <= maxmemory {
+            break;
+        }
+        sleep(Duration::from_millis(50));
+    }
+
+    let evicted = get_metric(&mut conn, "keys_evicted")?;
+    assert!(evicted > 0);
+    let keys: Vec<String> = conn.keys("{test_ns}:*")?;
+    assert_eq!(200 - evicted as usize, keys.len());
+    let metas: Vec<String> = conn.keys("meta_{test_ns}:*")?;
+    assert_eq!(keys.len(), metas.len());
+    // the evicted keys are groomed from the index sets, which list exactly the keys left
+    let mut members: Vec<String> = conn.smembers("idx_{test_ns}:first:0")?;
+    members.extend(conn.smembers::<_, Vec<String>>("idx_{test_ns}:first:1")?);
+    let keys = keys
+        .into_iter()
+        .map(|key| key["{test_ns}:".len()..].to_string())
+        .sorted()
+        .collect_vec();
+    assert_eq!(keys, members.into_iter().sorted().collect_vec());
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_msetex_index_pairs(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg("PAIRS")
+        .arg("k1")
+        .arg("v1")
+        .arg(2)
+        .arg("first")
+        .arg("x")
+        .arg("second")
+        .arg("y")
+        .arg("k2")
+        .arg("v2")
+        .arg(1)
+        .arg("second")
+        .arg("y")
+        .arg("k3")
+        .arg("v3")
+        .arg(0)
+        .query(&mut conn)?;
+
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:first:x")?;
+    let members: Vec<String> = conn.smembers("idx_{test_ns}:second:y")?;
+    assert_eq!(vec!["k1", "k2"], members.into_iter().sorted().collect_vec());
+    assert_key_value(&mut conn, "v3", "{test_ns}:k3")?;
+    // data and meta of each key along with the two index sets, the version counter and the index kinds
+    assert_keys_count(&mut conn, 10)?;
+    assert!(conn.exists("version_{test_ns}")?);
+    assert!(conn.exists("indices_{test_ns}")?);
+
+    let invalid: RedisResult<()> = redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg("PAIRS")
+        .arg("k4")
+        .arg("v4")
+        .arg(2)
+        .arg("first")
+        .arg("x")
+        .query(&mut conn);
+    assert!(invalid.is_err());
 
-    assert_keys_count(&mut conn, 0)
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_msetex_tags(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(1)
+        .arg("PAIRS")
+        .arg("k1")
+        .arg("v1")
+        .arg(3)
+        .arg("tag")
+        .arg("a")
+        .arg("tag")
+        .arg("b")
+        .arg("city")
+        .arg("x")
+        .query(&mut conn)?;
+
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:tag:a")?;
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:tag:b")?;
+    let values: Vec<String> = redis::cmd("MAP.GET_BY_INDEX").arg("test_ns").arg("tag").arg("b").query(&mut conn)?;
+    assert_eq!(vec!["v1"], values);
+    let (_, indices): (String, Vec<String>) = redis::cmd("MAP.GET").arg("test_ns").arg("k1").arg("WITHINDICES").query(&mut conn)?;
+    let pairs = indices.into_iter().tuples::<(String, String)>().sorted().collect_vec();
+    assert_eq!(
+        vec![
+            ("city".to_string(), "x".to_string()),
+            ("tag".to_string(), "a".to_string()),
+            ("tag".to_string(), "b".to_string())
+        ],
+        pairs
+    );
+
+    let updated: i64 = redis::cmd("MAP.UPDATE_INDICES")
+        .arg("test_ns")
+        .arg("k1")
+        .arg("SET")
+        .arg("tag")
+        .arg("b")
+        .arg("SET")
+        .arg("tag")
+        .arg("c")
+        .query(&mut conn)?;
+    assert_eq!(1, updated);
+    assert_keys_count(&mut conn, 7)?;
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:tag:c")?;
+
+    // expiry removes the key from every index set of the tag
+    sleep(Duration::from_secs(2));
+    assert!(conn.exists("version_{test_ns}")?);
+    assert!(conn.exists("indices_{test_ns}")?);
+    assert_keys_count(&mut conn, 2)?;
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_range_by_index(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSET_INDEXED")
+        .arg("test_ns")
+        .arg("NUMERIC")
+        .arg("price")
+        .arg(2)
+        .arg("city")
+        .arg("price")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .arg(10)
+        .arg("k2")
+        .arg("v2")
+        .arg("x")
+        .arg(20.5)
+        .arg("k3")
+        .arg("v3")
+        .arg("y")
+        .arg(30)
+        .query(&mut conn)?;
+
+    let values: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("price")
+        .arg(10)
+        .arg(30)
+        .query(&mut conn)?;
+    assert_eq!(vec!["v1", "v2", "v3"], values);
+    let keys: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("price")
+        .arg("(10")
+        .arg("+inf")
+        .arg("REV")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k3", "k2"], keys);
+    let keys: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("price")
+        .arg("-inf")
+        .arg("+inf")
+        .arg("LIMIT")
+        .arg(1)
+        .arg(1)
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k2"], keys);
+    let (_, indices): (String, Vec<String>) = redis::cmd("MAP.GET").arg("test_ns").arg("k1").arg("WITHINDICES").query(&mut conn)?;
+    assert_eq!(vec!["city", "x", "price", "10"], indices);
+
+    let invalid: RedisResult<()> = redis::cmd("MAP.MSET_INDEXED")
+        .arg("test_ns")
+        .arg("NUMERIC")
+        .arg("price")
+        .arg(1)
+        .arg("price")
+        .arg("k4")
+        .arg("v4")
+        .arg("cheap")
+        .query(&mut conn);
+    assert!(invalid.is_err());
+    // rejected by ZADD as well: overflowing to an infinity, underflowing to zero or not a number
+    for value in ["1e400", "-1e400", "1e-400", "infinity", "nan"] {
+        let invalid: RedisResult<()> = redis::cmd("MAP.MSET_INDEXED")
+            .arg("test_ns")
+            .arg("NUMERIC")
+            .arg("price")
+            .arg(1)
+            .arg("price")
+            .arg("k4")
+            .arg("v4")
+            .arg(5)
+            .arg("k5")
+            .arg("v5")
+            .arg(value)
+            .query(&mut conn);
+        assert_eq!(
+            Some("line 2 (key [ k5 ]): numeric index value must be a number!"),
+            invalid.unwrap_err().detail()
+        );
+        assert!(!conn.exists("{test_ns}:k4")?);
+    }
+    redis::cmd("MAP.MSET_INDEXED")
+        .arg("test_ns")
+        .arg("NUMERIC")
+        .arg("price")
+        .arg(1)
+        .arg("price")
+        .arg("k4")
+        .arg("v4")
+        .arg("-inf")
+        .query(&mut conn)?;
+
+    let updated: i64 = redis::cmd("MAP.UPDATE_INDICES")
+        .arg("test_ns")
+        .arg("k1")
+        .arg("SET")
+        .arg("price")
+        .arg(40)
+        .query(&mut conn)?;
+    assert_eq!(1, updated);
+    let keys: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("price")
+        .arg(25)
+        .arg("+inf")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k3", "k1"], keys);
+
+    let removed: i64 = redis::cmd("MAP.MREM")
+        .arg("test_ns")
+        .arg("k1")
+        .arg("k2")
+        .arg("k3")
+        .arg("k4")
+        .query(&mut conn)?;
+    assert_eq!(4, removed);
+    assert!(conn.exists("version_{test_ns}")?);
+    assert!(conn.exists("indices_{test_ns}")?);
+    assert_keys_count(&mut conn, 2)?;
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_index_kinds(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSET_INDEXED")
+        .arg("test_ns")
+        .arg("NUMERIC")
+        .arg("price")
+        .arg(1)
+        .arg("price")
+        .arg("k1")
+        .arg("v1")
+        .arg(10)
+        .query(&mut conn)?;
+
+    // a later batch can't split the values of the index between its kinds
+    match redis::cmd("MAP.MSET_INDEXED")
+        .arg("test_ns")
+        .arg(1)
+        .arg("price")
+        .arg("k2")
+        .arg("v2")
+        .arg(20)
+        .query(&mut conn)
+    {
+        Ok(()) => panic!("Expected index kind mismatch"),
+        Err(err) => assert_eq!(Some("index [ price ] is a NUMERIC index, not EXACT!"), err.detail()),
+    }
+    let invalid: RedisResult<()> = redis::cmd("MAP.MSET_INDEXED")
+        .arg("test_ns")
+        .arg("LEX")
+        .arg("price")
+        .arg("PAIRS")
+        .arg("k2")
+        .arg("v2")
+        .arg(1)
+        .arg("price")
+        .arg(20)
+        .query(&mut conn);
+    assert!(invalid.is_err());
+    assert!(!conn.exists("{test_ns}:k2")?);
+
+    // an entry without the index gets it as the kind recorded for the namespace
+    redis::cmd("MAP.MSET_INDEXED")
+        .arg("test_ns")
+        .arg(0)
+        .arg("k3")
+        .arg("v3")
+        .query(&mut conn)?;
+    let updated: i64 = redis::cmd("MAP.UPDATE_INDICES")
+        .arg("test_ns")
+        .arg("k3")
+        .arg("SET")
+        .arg("price")
+        .arg(30)
+        .query(&mut conn)?;
+    assert_eq!(1, updated);
+    let keys: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("price")
+        .arg("-inf")
+        .arg("+inf")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k1", "k3"], keys);
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_lex_index(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg("LEX")
+        .arg("postcode")
+        .arg(1)
+        .arg("postcode")
+        .arg("k1")
+        .arg("v1")
+        .arg("60200")
+        .arg("k2")
+        .arg("v2")
+        .arg("602")
+        .arg("k3")
+        .arg("v3")
+        .arg("61000")
+        .arg("k4")
+        .arg("v4")
+        .arg("11000")
+        .query(&mut conn)?;
+
+    let values: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("postcode")
+        .arg("PREFIX")
+        .arg("602")
+        .query(&mut conn)?;
+    assert_eq!(vec!["v2", "v1"], values);
+    let keys: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("postcode")
+        .arg("(602")
+        .arg("[61000")
+        .arg("BYLEX")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k1", "k3"], keys);
+    let keys: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("postcode")
+        .arg("-")
+        .arg("(602")
+        .arg("BYLEX")
+        .arg("REV")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k4"], keys);
+
+    let invalid: RedisResult<()> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("postcode")
+        .arg("PREFIX")
+        .arg("602")
+        .arg("BYLEX")
+        .query(&mut conn);
+    assert_eq!(Some("syntax error"), invalid.unwrap_err().detail());
+    // the terminator would match the members of any key
+    for (first, second, by_lex) in [("PREFIX", "60200\0", false), ("[60200\0", "+", true), ("-", "(60200\0k", true)] {
+        let mut cmd = redis::cmd("MAP.RANGE_BY_INDEX");
+        cmd.arg("test_ns").arg("postcode").arg(first).arg(second);
+        if by_lex {
+            cmd.arg("BYLEX");
+        }
+        let invalid: RedisResult<Vec<String>> = cmd.query(&mut conn);
+        assert!(invalid.is_err(), "{:?} {:?}", first, second);
+    }
+
+    // a value spelled PREFIX is looked up the same as any other
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg("LEX")
+        .arg("postcode")
+        .arg(1)
+        .arg("postcode")
+        .arg("k5")
+        .arg("v5")
+        .arg("PREFIX")
+        .query(&mut conn)?;
+    let keys: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("postcode")
+        .arg("[PREFIX")
+        .arg("[PREFIX")
+        .arg("BYLEX")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k5"], keys);
+    let keys: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("postcode")
+        .arg("PREFIX")
+        .arg("PREFIX")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k5"], keys);
+    let removed: i64 = redis::cmd("MAP.MREM").arg("test_ns").arg("k5").query(&mut conn)?;
+    assert_eq!(1, removed);
+
+    let updated: i64 = redis::cmd("MAP.UPDATE_INDICES")
+        .arg("test_ns")
+        .arg("k3")
+        .arg("SET")
+        .arg("postcode")
+        .arg("60201")
+        .query(&mut conn)?;
+    assert_eq!(1, updated);
+    let keys: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("postcode")
+        .arg("PREFIX")
+        .arg("6020")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k1", "k3"], keys);
+
+    let removed: i64 = redis::cmd("MAP.MREM")
+        .arg("test_ns")
+        .arg("k1")
+        .arg("k2")
+        .arg("k3")
+        .arg("k4")
+        .query(&mut conn)?;
+    assert_eq!(4, removed);
+    assert!(conn.exists("version_{test_ns}")?);
+    assert!(conn.exists("indices_{test_ns}")?);
+    assert_keys_count(&mut conn, 2)?;
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_geo_index(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(1)
+        .arg("GEO")
+        .arg("home")
+        .arg(1)
+        .arg("home")
+        .arg("brno")
+        .arg("v1")
+        .arg("16.6068,49.1951")
+        .arg("prague")
+        .arg("v2")
+        .arg("14.4205,50.0880")
+        .query(&mut conn)?;
+
+    let values: Vec<String> = redis::cmd("MAP.GEO_BY_INDEX")
+        .arg("test_ns")
+        .arg("home")
+        .arg(16.6)
+        .arg(49.2)
+        .arg("BYRADIUS")
+        .arg(50)
+        .arg("km")
+        .query(&mut conn)?;
+    assert_eq!(vec!["v1"], values);
+    let keys: Vec<String> = redis::cmd("MAP.GEO_BY_INDEX")
+        .arg("test_ns")
+        .arg("home")
+        .arg(14.5)
+        .arg(50.0)
+        .arg("BYBOX")
+        .arg(400)
+        .arg(200)
+        .arg("km")
+        .arg("ASC")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["prague", "brno"], keys);
+
+    // moving the point to vienna
+    let updated: i64 = redis::cmd("MAP.UPDATE_INDICES")
+        .arg("test_ns")
+        .arg("brno")
+        .arg("SET")
+        .arg("home")
+        .arg("16.3738,48.2082")
+        .query(&mut conn)?;
+    assert_eq!(1, updated);
+    let keys: Vec<String> = redis::cmd("MAP.GEO_BY_INDEX")
+        .arg("test_ns")
+        .arg("home")
+        .arg(16.6)
+        .arg(49.2)
+        .arg("BYRADIUS")
+        .arg(50)
+        .arg("km")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert!(keys.is_empty());
+    let keys: Vec<String> = redis::cmd("MAP.GEO_BY_INDEX")
+        .arg("test_ns")
+        .arg("home")
+        .arg(16.4)
+        .arg(48.2)
+        .arg("BYRADIUS")
+        .arg(50)
+        .arg("km")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["brno"], keys);
+
+    let removed: i64 = redis::cmd("MAP.MREM").arg("test_ns").arg("prague").query(&mut conn)?;
+    assert_eq!(1, removed);
+    let members: Vec<String> = conn.zrange("geoidx_{test_ns}:home", 0, -1)?;
+    assert_eq!(vec!["brno"], members);
+
+    let invalid: RedisResult<()> = redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(1)
+        .arg("GEO")
+        .arg("home")
+        .arg(1)
+        .arg("home")
+        .arg("nowhere")
+        .arg("v3")
+        .arg("16.6068")
+        .query(&mut conn);
+    assert!(invalid.is_err());
+    // an underflowing coordinate, which GEOADD rejects, fails the batch before its first line gets written
+    let invalid: RedisResult<()> = redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(1)
+        .arg("GEO")
+        .arg("home")
+        .arg(1)
+        .arg("home")
+        .arg("vienna")
+        .arg("v3")
+        .arg("16.3725,48.2083")
+        .arg("nowhere")
+        .arg("v4")
+        .arg("1e-400,49.19")
+        .query(&mut conn);
+    assert_eq!(
+        Some("line 2 (key [ nowhere ]): geo index value must be <longitude>,<latitude>!"),
+        invalid.unwrap_err().detail()
+    );
+    assert!(!conn.exists("{test_ns}:vienna")?);
+
+    // expiry removes the points from the geo set
+    sleep(Duration::from_secs(2));
+    assert!(conn.exists("version_{test_ns}")?);
+    assert!(conn.exists("indices_{test_ns}")?);
+    assert_keys_count(&mut conn, 2)?;
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_msetex_indexed(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(2)
+        .arg("first")
+        .arg("second")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .arg("y")
+        .arg("k2")
+        .arg("v2")
+        .arg("w")
+        .arg("z")
+        .query(&mut conn)?;
+
+    let values: Vec<String> = conn.get(vec!["{test_ns}:k1", "{test_ns}:k2"])?;
+    assert_eq!(vec!["v1", "v2"], values);
+
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:first:x")?;
+    assert_members(&mut conn, vec!["k2"], "idx_{test_ns}:first:w")?;
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:second:y")?;
+    assert_members(&mut conn, vec!["k2"], "idx_{test_ns}:second:z")?;
+
+    let meta_k1: HashMap<String, String> = conn.hgetall("meta_{test_ns}:k1")?;
+    assert_eq!(vec!["first", "second"], meta_k1.keys().sorted().collect::<Vec<&String>>());
+
+    assert_eq!("x", meta_k1.get("first").unwrap());
+    assert_eq!("y", meta_k1.get("second").unwrap());
+
+    let meta_k2: HashMap<String, String> = conn.hgetall("meta_{test_ns}:k2")?;
+    assert_eq!(vec!["first", "second"], meta_k2.keys().sorted().collect::<Vec<&String>>());
+
+    assert_eq!("w", meta_k2.get("first").unwrap());
+    assert_eq!("z", meta_k2.get("second").unwrap());
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_msetex_indexed_update(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(2)
+        .arg("first")
+        .arg("second")
+        .arg("k")
+        .arg("v")
+        .arg("x")
+        .arg("y")
+        .query(&mut conn)?;
+
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(2)
+        .arg("second")
+        .arg("third")
+        .arg("k")
+        .arg("v")
+        .arg("y2")
+        .arg("z")
+        .query(&mut conn)?;
+
+    assert_key_value(&mut conn, "v", "{test_ns}:k")?;
+
+    assert!(!conn.exists("idx_{test_ns}:first:x")?);
+    assert!(!conn.exists("idx_{test_ns}:second:y")?);
+    assert_members(&mut conn, vec!["k"], "idx_{test_ns}:second:y2")?;
+    assert_members(&mut conn, vec!["k"], "idx_{test_ns}:third:z")
+}
+
+#[redis_test(loaded_module)]
+fn test_msetex_index_unique_validation(mut conn: Connection) -> RedisResult<()> {
+    match redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(1)
+        .arg(3)
+        .arg("duplicate")
+        .arg("duplicate")
+        .arg("third")
+        .arg("k")
+        .arg("v")
+        .arg("x")
+        .arg("y")
+        .arg("z")
+        .query(&mut conn)
+    {
+        Ok(()) => panic!("Expected validation failure"),
+        Err(err) => assert_eq!(Some("index names must be unique!"), err.detail()),
+    }
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_msetex_indexed_load(mut conn: Connection) -> RedisResult<()> {
+    for i in 1..10_000 {
+        redis::cmd("MAP.MSETEX_INDEXED")
+            .arg("test_ns")
+            .arg(1)
+            .arg(3)
+            .arg("first")
+            .arg("second")
+            .arg("third")
+            .arg(format!("k{}", i))
+            .arg(format!("v{}", i))
+            .arg(format!("{}", i / 2))
+            .arg(format!("{}", i / 3))
+            .arg(format!("{}", i / 4))
+            .query(&mut conn)?;
+    }
+
+    thread::sleep(Duration::from_secs(5));
+
+    // nothing left but the version counter and the index kinds of the namespace
+    let last_version: i64 = conn.get("version_{test_ns}")?;
+    assert_eq!(9_999, last_version);
+    let index_kinds: HashMap<String, String> = conn.hgetall("indices_{test_ns}")?;
+    assert_eq!(vec!["first", "second", "third"], index_kinds.keys().sorted().collect_vec());
+    assert_keys_count(&mut conn, 2)
+}
+
+#[redis_test(loaded_module)]
+fn test_msetex_indexed_randomized(mut conn: Connection) -> RedisResult<()> {
+    let mut rng = rand::thread_rng();
+
+    for i in 1..10_000 {
+        let idx_base = rng.gen_range(0, 1000);
+        redis::cmd("MAP.MSETEX_INDEXED")
+            .arg("test_ns")
+            .arg(1)
+            .arg(3)
+            .arg(idx_base)
+            .arg(idx_base + 1)
+            .arg(idx_base + 2)
+            .arg(format!("{}", rng.gen_range(0, 1000)))
+            .arg(format!("v{}", i))
+            .arg(rng.gen_range(0, 100))
+            .arg(rng.gen_range(0, 100))
+            .arg(rng.gen_range(0, 100))
+            .query(&mut conn)?;
+    }
+
+    thread::sleep(Duration::from_secs(5));
+
+    // nothing left but the version counter and the index kinds of the namespace
+    let last_version: i64 = conn.get("version_{test_ns}")?;
+    assert_eq!(9_999, last_version);
+    let index_kinds: HashMap<String, String> = conn.hgetall("indices_{test_ns}")?;
+    assert!(index_kinds.values().all(|kind| kind == "EXACT"));
+    assert_keys_count(&mut conn, 2)
+}
+
+#[redis_test(loaded_module)]
+fn test_foreign_keys_expiry(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(1)
+        .arg("first")
+        .arg("k")
+        .arg("v")
+        .arg("x")
+        .query(&mut conn)?;
+
+    conn.set_ex("foreign", "v", 1)?;
+    conn.set_ex("foreign:k", "v", 1)?;
+    conn.set_ex("test_ns:other", "v", 1)?;
+
+    sleep(Duration::from_secs(2));
+
+    assert_key_value(&mut conn, "v", "{test_ns}:k")?;
+    assert_members(&mut conn, vec!["k"], "idx_{test_ns}:first:x")?;
+
+    assert_eq!(0, get_metric(&mut conn, "meta_missing")?);
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_binary_safe(mut conn: Connection) -> RedisResult<()> {
+    let key: &[u8] = b"k\xff\x00";
+    let value: &[u8] = b"\x00\x9f\x92\x96";
+    let idx_val: &[u8] = b"\xc3\x28";
+
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(1)
+        .arg("first")
+        .arg(key)
+        .arg(value)
+        .arg(idx_val)
+        .query(&mut conn)?;
+
+    let values: Vec<Vec<u8>> = redis::cmd("MAP.GET_BY_INDEX")
+        .arg("test_ns")
+        .arg("first")
+        .arg(idx_val)
+        .query(&mut conn)?;
+    assert_eq!(vec![value.to_vec()], values);
+
+    let removed: i64 = redis::cmd("MAP.MREM").arg("test_ns").arg(key).query(&mut conn)?;
+    assert_eq!(1, removed);
+
+    assert!(conn.exists("version_{test_ns}")?);
+    assert!(conn.exists("indices_{test_ns}")?);
+    assert_keys_count(&mut conn, 2)
+}
+
+#[redis_test(loaded_module)]
+fn test_separator_escaping(mut conn: Connection) -> RedisResult<()> {
+    for (namespace, idx, value) in &[("a:b", "c", "v1"), ("a", "b:c", "v2")] {
+        redis::cmd("MAP.MSETEX_INDEXED")
+            .arg(*namespace)
+            .arg(10)
+            .arg(1)
+            .arg(*idx)
+            .arg("k")
+            .arg(*value)
+            .arg("x")
+            .query(&mut conn)?;
+    }
+
+    assert_key_value(&mut conn, "v1", "{a:b}:k")?;
+    assert_key_value(&mut conn, "v2", "{a}:k")?;
+    assert_members(&mut conn, vec!["k"], "idx_{a:b}:c:x")?;
+    assert_members(&mut conn, vec!["k"], "idx_{a}:b\\:c:x")?;
+
+    let values: Vec<String> = redis::cmd("MAP.GET_BY_INDEX").arg("a:b").arg("c").arg("x").query(&mut conn)?;
+    assert_eq!(vec!["v1"], values);
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_migrate_layout(mut conn: Connection) -> RedisResult<()> {
+    conn.set_ex("a:b:k1", "v1", 10)?;
+    conn.hset("meta_a:b:k1", "i:j", "x")?;
+    conn.sadd("idx_a:b:i:j:x", "k1")?;
+    // key b:k2 of namespace a, sharing the legacy prefix of namespace a:b
+    conn.set_ex("a:b:k2", "v2", 10)?;
+    conn.hset("meta_a:b:k2", "i", "y")?;
+    conn.sadd("idx_a:i:y", "b:k2")?;
+    // another application's key
+    conn.set("a:b:user", "foreign")?;
+
+    let report: (String, i64, String, i64) = redis::cmd("MAP.MIGRATE_LAYOUT").arg("a:b").query(&mut conn)?;
+    assert_eq!(("migrated".to_string(), 1, "ambiguous".to_string(), 1), report);
+
+    assert_key_value(&mut conn, "v1", "{a:b}:k1")?;
+    assert!(conn.exists("meta_{a:b}:k1")?);
+    assert_members(&mut conn, vec!["k1"], "idx_{a:b}:i\\:j:x")?;
+    assert_key_value(&mut conn, "foreign", "a:b:user")?;
+    assert_key_value(&mut conn, "v2", "a:b:k2")?;
+
+    let report: (String, i64, String, i64) = redis::cmd("MAP.MIGRATE_LAYOUT").arg("a").query(&mut conn)?;
+    assert_eq!(("migrated".to_string(), 1, "ambiguous".to_string(), 0), report);
+
+    assert_key_value(&mut conn, "v2", "{a}:b:k2")?;
+    assert_members(&mut conn, vec!["b:k2"], "idx_{a}:i:y")?;
+    assert_key_value(&mut conn, "foreign", "a:b:user")?;
+    // data and meta of both entries, their index sets and the foreign key
+    assert_keys_count(&mut conn, 7)
+}
+
+#[redis_test(loaded_module)]
+fn test_cluster_key_specs(mut conn: Connection) -> RedisResult<()> {
+    let keys: Vec<String> = redis::cmd("COMMAND")
+        .arg("GETKEYS")
+        .arg("MAP.GET_BY_INDEX")
+        .arg("test_ns")
+        .arg("first")
+        .arg("x")
+        .query(&mut conn)?;
+    assert_eq!(vec!["test_ns"], keys);
+
+    match redis::cmd("MAP.MREM").arg("test{ns}").arg("k").query(&mut conn) {
+        Ok(()) => panic!("Expected validation failure"),
+        Err(err) => assert_eq!(Some("namespace must not contain '{' or '}'!"), err.detail()),
+    }
+    match redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("")
+        .arg(10)
+        .arg(1)
+        .arg("first")
+        .arg("k")
+        .arg("v")
+        .arg("x")
+        .query(&mut conn)
+    {
+        Ok(()) => panic!("Expected validation failure"),
+        Err(err) => assert_eq!(Some("namespace must not be empty!"), err.detail()),
+    }
+    assert_keys_count(&mut conn, 0)?;
+
+    Ok(())
+}
+
+#[redis_test(cluster, loaded_module)]
+fn test_cluster_hash_slot(mut conn: Connection) -> RedisResult<()> {
+    for namespace in &["a:b", "a\\b", "plain"] {
+        redis::cmd("MAP.MSETEX_INDEXED")
+            .arg(*namespace)
+            .arg(10)
+            .arg(1)
+            .arg("i:j")
+            .arg("k")
+            .arg("v")
+            .arg("x")
+            .query(&mut conn)?;
+
+        let namespace_slot: i64 = redis::cmd("CLUSTER").arg("KEYSLOT").arg(*namespace).query(&mut conn)?;
+        let hash_tag = format!("{{{}}}", namespace);
+        let keys: Vec<String> = conn.keys("*")?;
+        let keys = keys.into_iter().filter(|key| key.contains(&hash_tag)).collect_vec();
+        // data, meta, index set, version counter and index kinds
+        assert_eq!(5, keys.len());
+        for key in keys {
+            let slot: i64 = redis::cmd("CLUSTER").arg("KEYSLOT").arg(&key).query(&mut conn)?;
+            assert_eq!(namespace_slot, slot, "key [ {} ] of namespace [ {} ]", key, namespace);
+        }
+
+        let values: Vec<String> = redis::cmd("MAP.GET_BY_INDEX")
+            .arg(*namespace)
+            .arg("i:j")
+            .arg("x")
+            .query(&mut conn)?;
+        assert_eq!(vec!["v"], values);
+    }
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_msetex_atomic_batch(mut conn: Connection) -> RedisResult<()> {
+    conn.set("idx_{test_ns}:first:y", "not a set")?;
+
+    match redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .arg("k2")
+        .arg("v2")
+        .arg("y")
+        .query(&mut conn)
+    {
+        Ok(()) => panic!("Expected batch failure"),
+        Err(err) => assert!(err.detail().unwrap().starts_with("line 2 (key [ k2 ])")),
+    }
+
+    assert!(!conn.exists("{test_ns}:k1")?);
+    assert!(!conn.exists("idx_{test_ns}:first:x")?);
+    // not even the index kinds get recorded by a failing batch
+    assert!(!conn.exists("indices_{test_ns}")?);
+    assert_keys_count(&mut conn, 1)
+}
+
+#[redis_test(loaded_module)]
+fn test_get_and_mget(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .arg("k2")
+        .arg("v2")
+        .arg("y")
+        .query(&mut conn)?;
+
+    let value: String = redis::cmd("MAP.GET").arg("test_ns").arg("k1").query(&mut conn)?;
+    assert_eq!("v1", value);
+
+    let missing: Option<String> = redis::cmd("MAP.GET").arg("test_ns").arg("nope").query(&mut conn)?;
+    assert_eq!(None, missing);
+
+    let values: Vec<Option<String>> = redis::cmd("MAP.MGET")
+        .arg("test_ns")
+        .arg(3)
+        .arg("k2")
+        .arg("nope")
+        .arg("k1")
+        .query(&mut conn)?;
+    assert_eq!(vec![Some("v2".to_string()), None, Some("v1".to_string())], values);
+
+    let (value, ttl, indices): (String, i64, Vec<String>) = redis::cmd("MAP.GET")
+        .arg("test_ns")
+        .arg("k2")
+        .arg("WITHTTL")
+        .arg("WITHINDICES")
+        .query(&mut conn)?;
+    assert_eq!("v2", value);
+    assert!(ttl > 0 && ttl <= 10);
+    assert_eq!(vec!["first", "y"], indices);
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_query(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(2)
+        .arg("city")
+        .arg("country")
+        .arg("mendel")
+        .arg("v1")
+        .arg("Brno")
+        .arg("Czechia")
+        .arg("einstein")
+        .arg("v2")
+        .arg("Ulm")
+        .arg("Germany")
+        .arg("kafka")
+        .arg("v3")
+        .arg("Prague")
+        .arg("Czechia")
+        .query(&mut conn)?;
+
+    let values: Vec<String> = redis::cmd("MAP.QUERY")
+        .arg("test_ns")
+        .arg("AND")
+        .arg(2)
+        .arg("city")
+        .arg("Brno")
+        .arg("country")
+        .arg("Czechia")
+        .query(&mut conn)?;
+    assert_eq!(vec!["v1"], values);
+
+    let keys: Vec<String> = redis::cmd("MAP.QUERY")
+        .arg("test_ns")
+        .arg("OR")
+        .arg(2)
+        .arg("city")
+        .arg("Ulm")
+        .arg("country")
+        .arg("Czechia")
+        .arg("NOT")
+        .arg(1)
+        .arg("city")
+        .arg("Prague")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["einstein", "mendel"], keys.into_iter().sorted().collect_vec());
+
+    let keys: Vec<String> = redis::cmd("MAP.QUERY")
+        .arg("test_ns")
+        .arg("AND")
+        .arg(2)
+        .arg("country")
+        .arg("Czechia")
+        .arg("city")
+        .arg("Prague")
+        .arg("NOT")
+        .arg(2)
+        .arg("city")
+        .arg("Brno")
+        .arg("country")
+        .arg("Germany")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["kafka"], keys);
+    let keys: Vec<String> = redis::cmd("MAP.QUERY")
+        .arg("test_ns")
+        .arg("AND")
+        .arg(1)
+        .arg("country")
+        .arg("Czechia")
+        .arg("NOT")
+        .arg(1)
+        .arg("city")
+        .arg("Prague")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["mendel"], keys);
+    let keys: Vec<String> = redis::cmd("MAP.QUERY")
+        .arg("test_ns")
+        .arg("AND")
+        .arg(1)
+        .arg("country")
+        .arg("Czechia")
+        .arg("NOT")
+        .arg(3)
+        .arg("city")
+        .arg("Brno")
+        .arg("city")
+        .arg("Prague")
+        .arg("city")
+        .arg("Vienna")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert!(keys.is_empty());
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_get_by_index_paged(mut conn: Connection) -> RedisResult<()> {
+    let mut cmd = redis::cmd("MAP.MSETEX_INDEXED");
+    cmd.arg("test_ns").arg(10).arg(1).arg("first");
+    for i in 0..1000 {
+        cmd.arg(format!("k{}", i)).arg(format!("v{}", i)).arg("x");
+    }
+    cmd.query(&mut conn)?;
+
+    let mut values = vec![];
+    let mut cursor = "0".to_string();
+    loop {
+        let (next, page): (String, Vec<String>) = redis::cmd("MAP.GET_BY_INDEX")
+            .arg("test_ns")
+            .arg("first")
+            .arg("x")
+            .arg("CURSOR")
+            .arg(&cursor)
+            .arg("COUNT")
+            .arg(100)
+            .query(&mut conn)?;
+        assert!(page.len() < 1000);
+        values.extend(page);
+        if next == "0" {
+            break;
+        }
+        cursor = next;
+    }
+
+    assert_eq!(1000, values.into_iter().unique().count());
+
+    for count in [0, -1] {
+        let invalid: RedisResult<(String, Vec<String>)> = redis::cmd("MAP.GET_BY_INDEX")
+            .arg("test_ns")
+            .arg("first")
+            .arg("x")
+            .arg("COUNT")
+            .arg(count)
+            .query(&mut conn);
+        assert_eq!(Some("COUNT must be positive!"), invalid.unwrap_err().detail());
+    }
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_get_by_index_with_keys(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .query(&mut conn)?;
+
+    let pairs: Vec<(String, (String, i64, Vec<String>))> = redis::cmd("MAP.GET_BY_INDEX")
+        .arg("test_ns")
+        .arg("first")
+        .arg("x")
+        .arg("WITHKEYS")
+        .arg("WITHTTL")
+        .arg("WITHINDICES")
+        .query(&mut conn)?;
+    assert_eq!(1, pairs.len());
+
+    let (key, (value, ttl, indices)) = &pairs[0];
+    assert_eq!("k1", key);
+    assert_eq!("v1", value);
+    assert!(*ttl > 0);
+    assert_eq!(&vec!["first", "x"], indices);
+
+    Ok(())
+}
+
+#[redis_test]
+fn test_lazy_repair_on_read(mut conn: Connection) -> RedisResult<()> {
+    load_module_with_args(&mut conn, &["LAZY_REPAIR"])?;
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .arg("k2")
+        .arg("v2")
+        .arg("x")
+        .arg("k3")
+        .arg("v3")
+        .arg("x")
+        .query(&mut conn)?;
+
+    // data key gone behind the module's back, meta still around
+    conn.del("{test_ns}:k1")?;
+    // both data key and meta gone
+    conn.del(vec!["{test_ns}:k2", "meta_{test_ns}:k2"])?;
+
+    let keys: Vec<String> = redis::cmd("MAP.GET_BY_INDEX")
+        .arg("test_ns")
+        .arg("first")
+        .arg("x")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k3"], keys);
+
+    assert_members(&mut conn, vec!["k3"], "idx_{test_ns}:first:x")?;
+    assert!(!conn.exists("meta_{test_ns}:k1")?);
+
+    assert_eq!(2, get_metric(&mut conn, "stale_members_repaired")?);
+
+    // lex members hold the value ahead of the key, they get groomed all the same
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg("LEX")
+        .arg("postcode")
+        .arg(1)
+        .arg("postcode")
+        .arg("k4")
+        .arg("v4")
+        .arg("60200")
+        .query(&mut conn)?;
+    conn.del(vec!["{test_ns}:k4", "meta_{test_ns}:k4"])?;
+
+    let values: Vec<String> = redis::cmd("MAP.RANGE_BY_INDEX")
+        .arg("test_ns")
+        .arg("postcode")
+        .arg("PREFIX")
+        .arg("602")
+        .query(&mut conn)?;
+    assert!(values.is_empty());
+    assert!(!conn.exists("lexidx_{test_ns}:postcode")?);
+    assert_eq!(3, get_metric(&mut conn, "stale_members_repaired")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_writes_propagated_to_replica() -> RedisResult<()> {
+    with_replicated_redis_conns("test_writes_propagated_to_replica", |mut primary, mut replica| {
+        redis::cmd("MAP.MSETEX_INDEXED")
+            .arg("test_ns")
+            .arg(10)
+            .arg(1)
+            .arg("first")
+            .arg("k1")
+            .arg("v1")
+            .arg("x")
+            .arg("k2")
+            .arg("v2")
+            .arg("x")
+            .query(&mut primary)?;
+        let updated: i64 = redis::cmd("MAP.UPDATE_INDICES")
+            .arg("test_ns")
+            .arg("k2")
+            .arg("SET")
+            .arg("first")
+            .arg("y")
+            .query(&mut primary)?;
+        assert_eq!(1, updated);
+        let removed: i64 = redis::cmd("MAP.MREM").arg("test_ns").arg("k1").query(&mut primary)?;
+        assert_eq!(1, removed);
+
+        let acked: i64 = redis::cmd("WAIT").arg(1).arg(1000).query(&mut primary)?;
+        assert_eq!(1, acked);
+        assert_key_value(&mut replica, "v2", "{test_ns}:k2")?;
+        assert_members(&mut replica, vec!["k2"], "idx_{test_ns}:first:y")?;
+        assert!(!replica.exists("idx_{test_ns}:first:x")?);
+        assert!(!replica.exists("{test_ns}:k1")?);
+        assert!(!replica.exists("meta_{test_ns}:k1")?);
+        let ttl: i64 = replica.ttl("{test_ns}:k2")?;
+        assert!(ttl > 0 && ttl <= 10);
+
+        let values: Vec<String> = redis::cmd("MAP.GET_BY_INDEX")
+            .arg("test_ns")
+            .arg("first")
+            .arg("y")
+            .query(&mut replica)?;
+        assert_eq!(vec!["v2"], values);
+
+        Ok(())
+    })
+}
+
+#[redis_test(loaded_module)]
+fn test_no_repair_on_read_by_default(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .arg("k2")
+        .arg("v2")
+        .arg("x")
+        .query(&mut conn)?;
+    conn.del("{test_ns}:k1")?;
+
+    // the stale member is left out of the values, yet nothing gets written by the lookup
+    let values: Vec<String> = redis::cmd("MAP.GET_BY_INDEX")
+        .arg("test_ns")
+        .arg("first")
+        .arg("x")
+        .query(&mut conn)?;
+    assert_eq!(vec!["v2"], values);
+    let keys: Vec<String> = redis::cmd("MAP.GET_BY_INDEX")
+        .arg("test_ns")
+        .arg("first")
+        .arg("x")
+        .arg("KEYSONLY")
+        .query(&mut conn)?;
+    assert_eq!(vec!["k1", "k2"], keys.into_iter().sorted().collect_vec());
+    let members: Vec<String> = conn.smembers("idx_{test_ns}:first:x")?;
+    assert_eq!(vec!["k1", "k2"], members.into_iter().sorted().collect_vec());
+    assert!(conn.exists("meta_{test_ns}:k1")?);
+    assert_eq!(0, get_metric(&mut conn, "stale_members_repaired")?);
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_fsck(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .arg("k2")
+        .arg("v2")
+        .arg("x")
+        .arg("k3")
+        .arg("v3")
+        .arg("x")
+        .query(&mut conn)?;
+
+    // orphaned meta
+    conn.del("{test_ns}:k1")?;
+    // index member missing
+    conn.srem("idx_{test_ns}:first:x", "k2")?;
+    // dangling index member
+    conn.sadd("idx_{test_ns}:first:x", "bogus")?;
+    // data without meta
+    conn.del("meta_{test_ns}:k3")?;
+
+    let report: Vec<(String, i64)> = redis::cmd("MAP.FSCK").arg("NAMESPACE").arg("test_ns").query(&mut conn)?;
+    assert_eq!(
+        vec![
+            ("orphaned_meta".to_string(), 1),
+            ("missing_index_members".to_string(), 1),
+            // bogus along with k1 and k3 lacking data and meta respectively
+            ("dangling_index_members".to_string(), 3),
+            ("data_without_meta".to_string(), 1),
+            ("unused_index_kinds".to_string(), 0),
+        ],
+        report
+    );
+
+    let _: Vec<(String, i64)> = redis::cmd("MAP.FSCK")
+        .arg("NAMESPACE")
+        .arg("test_ns")
+        .arg("REPAIR")
+        .query(&mut conn)?;
+    assert_members(&mut conn, vec!["k2"], "idx_{test_ns}:first:x")?;
+    assert!(!conn.exists("meta_{test_ns}:k1")?);
+
+    let report: Vec<(String, i64)> = redis::cmd("MAP.FSCK").query(&mut conn)?;
+    assert_eq!(
+        vec![
+            ("orphaned_meta".to_string(), 0),
+            ("missing_index_members".to_string(), 0),
+            ("dangling_index_members".to_string(), 0),
+            ("data_without_meta".to_string(), 1),
+            ("unused_index_kinds".to_string(), 0),
+        ],
+        report
+    );
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_fsck_unused_index_kinds(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSET_INDEXED")
+        .arg("test_ns")
+        .arg("NUMERIC")
+        .arg("price")
+        .arg(2)
+        .arg("price")
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg(10)
+        .arg("x")
+        .query(&mut conn)?;
+    let removed: i64 = redis::cmd("MAP.MREM").arg("test_ns").arg("k1").query(&mut conn)?;
+    assert_eq!(1, removed);
+
+    // the kinds outlive the entries, so price can't be written as an exact index yet
+    let exact_price = |conn: &mut Connection| -> RedisResult<()> {
+        redis::cmd("MAP.MSET_INDEXED")
+            .arg("test_ns")
+            .arg(1)
+            .arg("price")
+            .arg("k2")
+            .arg("v2")
+            .arg("cheap")
+            .query(conn)
+    };
+    assert!(exact_price(&mut conn).is_err());
+
+    let report: Vec<(String, i64)> = redis::cmd("MAP.FSCK").arg("NAMESPACE").arg("test_ns").query(&mut conn)?;
+    assert_eq!(("unused_index_kinds".to_string(), 2), report[4]);
+    let report: Vec<(String, i64)> = redis::cmd("MAP.FSCK")
+        .arg("NAMESPACE")
+        .arg("test_ns")
+        .arg("REPAIR")
+        .query(&mut conn)?;
+    assert_eq!(("unused_index_kinds".to_string(), 2), report[4]);
+    assert!(!conn.exists("indices_{test_ns}")?);
+
+    exact_price(&mut conn)?;
+    let index_kinds: HashMap<String, String> = conn.hgetall("indices_{test_ns}")?;
+    assert_eq!(Some(&"EXACT".to_string()), index_kinds.get("price"));
+    let report: Vec<(String, i64)> = redis::cmd("MAP.FSCK").query(&mut conn)?;
+    assert_eq!(("unused_index_kinds".to_string(), 0), report[4]);
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_fsck_sorted_indices(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg("NUMERIC")
+        .arg("price")
+        .arg("LEX")
+        .arg("postcode")
+        .arg(2)
+        .arg("price")
+        .arg("postcode")
+        .arg("k1")
+        .arg("v1")
+        .arg(10)
+        .arg("602")
+        .arg("k2")
+        .arg("v2")
+        .arg(20)
+        .arg("603")
+        .arg("k3")
+        .arg("v3")
+        .arg(15)
+        .arg("604")
+        .query(&mut conn)?;
+
+    // index members missing
+    let _: () = conn.zrem("numidx_{test_ns}:price", "k1")?;
+    let _: () = conn.zrem("lexidx_{test_ns}:postcode", "603\0k2")?;
+    // dangling index members: a key without data, a value the meta doesn't record and a member without a key
+    let _: () = conn.zadd("numidx_{test_ns}:price", "bogus", 5)?;
+    let _: () = conn.zadd("lexidx_{test_ns}:postcode", "602\0k3", 0)?;
+    let _: () = conn.zadd("lexidx_{test_ns}:postcode", "garbage", 0)?;
+
+    let report: Vec<(String, i64)> = redis::cmd("MAP.FSCK").arg("NAMESPACE").arg("test_ns").query(&mut conn)?;
+    assert_eq!(
+        vec![
+            ("orphaned_meta".to_string(), 0),
+            ("missing_index_members".to_string(), 2),
+            ("dangling_index_members".to_string(), 3),
+            ("data_without_meta".to_string(), 0),
+            ("unused_index_kinds".to_string(), 0),
+        ],
+        report
+    );
+
+    let _: Vec<(String, i64)> = redis::cmd("MAP.FSCK")
+        .arg("NAMESPACE")
+        .arg("test_ns")
+        .arg("REPAIR")
+        .query(&mut conn)?;
+    let members: Vec<String> = conn.zrange("numidx_{test_ns}:price", 0, -1)?;
+    assert_eq!(vec!["k1", "k3", "k2"], members);
+    let members: Vec<String> = conn.zrange("lexidx_{test_ns}:postcode", 0, -1)?;
+    assert_eq!(vec!["602\0k1", "603\0k2", "604\0k3"], members);
+
+    let report: Vec<(String, i64)> = redis::cmd("MAP.FSCK").query(&mut conn)?;
+    assert_eq!(
+        vec![
+            ("orphaned_meta".to_string(), 0),
+            ("missing_index_members".to_string(), 0),
+            ("dangling_index_members".to_string(), 0),
+            ("data_without_meta".to_string(), 0),
+            ("unused_index_kinds".to_string(), 0),
+        ],
+        report
+    );
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_metrics_info(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(1)
+        .arg("first")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .arg("k2")
+        .arg("v2")
+        .arg("x")
+        .query(&mut conn)?;
+
+    let _: Vec<String> = redis::cmd("MAP.MGET").arg("test_ns").arg(2).arg("k1").arg("k2").query(&mut conn)?;
+    let _: Vec<String> = redis::cmd("MAP.GET_BY_INDEX")
+        .arg("test_ns")
+        .arg("first")
+        .arg("x")
+        .query(&mut conn)?;
+    let _: Vec<String> = redis::cmd("MAP.GET_BY_INDEX")
+        .arg("test_ns")
+        .arg("first")
+        .arg("y")
+        .query(&mut conn)?;
+    let _: i64 = redis::cmd("MAP.MREM").arg("test_ns").arg("k1").query(&mut conn)?;
+
+    assert_eq!(2, get_metric(&mut conn, "sets")?);
+    assert_eq!(2, get_metric(&mut conn, "gets")?);
+    assert_eq!(1, get_metric(&mut conn, "removes")?);
+    assert_eq!(1, get_metric(&mut conn, "index_hits")?);
+    assert_eq!(1, get_metric(&mut conn, "index_misses")?);
+
+    // counters live in module memory rather than the keyspace, which holds just the remaining entry
+    // (data, meta and index set) along with the version counter and the index kinds of the namespace
+    assert!(conn.exists("version_{test_ns}")?);
+    assert!(conn.exists("indices_{test_ns}")?);
+    assert_keys_count(&mut conn, 5)
+}
+
+#[redis_test(loaded_module)]
+fn test_namespace_info(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(10)
+        .arg(2)
+        .arg("first")
+        .arg("second")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .arg("a")
+        .arg("k2")
+        .arg("v2")
+        .arg("y")
+        .arg("a")
+        .arg("k3")
+        .arg("v3")
+        .arg("y")
+        .arg("a")
+        .query(&mut conn)?;
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("other_ns")
+        .arg(1)
+        .arg(1)
+        .arg("first")
+        .arg("k")
+        .arg("v")
+        .arg("x")
+        .query(&mut conn)?;
+
+    let _: Vec<String> = redis::cmd("MAP.MGET").arg("test_ns").arg(2).arg("k1").arg("k4").query(&mut conn)?;
+    let _: i64 = redis::cmd("MAP.MREM").arg("test_ns").arg("k3").query(&mut conn)?;
+    sleep(Duration::from_secs(2));
+
+    let info: HashMap<String, redis::Value> = redis::cmd("MAP.INFO").arg("test_ns").query(&mut conn)?;
+    let integer = |name: &str| redis::from_redis_value::<i64>(&info[name]);
+    assert_eq!(2, integer("keys")?);
+    assert_eq!(0, integer("expirations")?);
+    assert_eq!(1, integer("removals")?);
+    assert_eq!(1, integer("hits")?);
+    assert_eq!(1, integer("misses")?);
+    assert!(integer("memory_bytes")? > 0);
+
+    let indices: HashMap<String, i64> = redis::from_redis_value(&info["indices"])?;
+    assert_eq!(2, indices["first"]);
+    assert_eq!(1, indices["second"]);
+
+    let info: HashMap<String, redis::Value> = redis::cmd("MAP.INFO").arg("other_ns").query(&mut conn)?;
+    assert_eq!(0, redis::from_redis_value::<i64>(&info["keys"])?);
+    assert_eq!(1, redis::from_redis_value::<i64>(&info["expirations"])?);
+
+    redis::cmd("MAP.MSET_INDEXED")
+        .arg("sorted_ns")
+        .arg("NUMERIC")
+        .arg("price")
+        .arg("LEX")
+        .arg("postcode")
+        .arg(2)
+        .arg("price")
+        .arg("postcode")
+        .arg("k1")
+        .arg("v1")
+        .arg(10)
+        .arg("602")
+        .arg("k2")
+        .arg("v2")
+        .arg(10)
+        .arg("603")
+        .query(&mut conn)?;
+    let info: HashMap<String, redis::Value> = redis::cmd("MAP.INFO").arg("sorted_ns").query(&mut conn)?;
+    // sorted indices count the entries having a value
+    let indices: HashMap<String, i64> = redis::from_redis_value(&info["indices"])?;
+    assert_eq!(2, indices["price"]);
+    assert_eq!(2, indices["postcode"]);
+
+    Ok(())
+}
+
+#[redis_test(loaded_module)]
+fn test_update_indices(mut conn: Connection) -> RedisResult<()> {
+    redis::cmd("MAP.MSETEX_INDEXED")
+        .arg("test_ns")
+        .arg(100)
+        .arg(2)
+        .arg("first")
+        .arg("second")
+        .arg("k1")
+        .arg("v1")
+        .arg("x")
+        .arg("a")
+        .query(&mut conn)?;
+
+    let updated: i64 = redis::cmd("MAP.UPDATE_INDICES")
+        .arg("test_ns")
+        .arg("k1")
+        .arg("SET")
+        .arg("first")
+        .arg("y")
+        .arg("DEL")
+        .arg("second")
+        .arg("SET")
+        .arg("third")
+        .arg("z")
+        .query(&mut conn)?;
+    assert_eq!(1, updated);
+
+    assert_key_value(&mut conn, "v1", "{test_ns}:k1")?;
+    let ttl: i64 = conn.ttl("{test_ns}:k1")?;
+    assert!(ttl > 90);
+    assert!(!conn.exists("idx_{test_ns}:first:x")?);
+    assert!(!conn.exists("idx_{test_ns}:second:a")?);
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:first:y")?;
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:third:z")?;
+
+    let (_, indices, version): (String, Vec<String>, i64) = redis::cmd("MAP.GET")
+        .arg("test_ns")
+        .arg("k1")
+        .arg("WITHINDICES")
+        .arg("WITHVERSION")
+        .query(&mut conn)?;
+    let pairs = indices
+        .chunks(2)
+        .map(|pair| (pair[0].as_str(), pair[1].as_str()))
+        .sorted()
+        .collect_vec();
+    assert_eq!(vec![("first", "y"), ("third", "z")], pairs);
+    assert_eq!(2, version);
+
+    // given the version, a stale writer is turned down the same as by a CAS write
+    let update = |conn: &mut Connection, version: i64| -> RedisResult<i64> {
+        redis::cmd("MAP.UPDATE_INDICES")
+            .arg("test_ns")
+            .arg("k1")
+            .arg("VERSION")
+            .arg(version)
+            .arg("SET")
+            .arg("first")
+            .arg("w")
+            .query(conn)
+    };
+    match update(&mut conn, 1) {
+        Ok(_) => panic!("Expected version mismatch"),
+        Err(err) => {
+            assert_eq!(Some("VERSIONMISMATCH"), err.code());
+            assert_eq!(Some("expected version 1, found 2"), err.detail());
+        }
+    }
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:first:y")?;
+    assert_eq!(1, update(&mut conn, 2)?);
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:first:w")?;
+    assert!(!conn.exists("idx_{test_ns}:first:y")?);
+
+    let updated: i64 = redis::cmd("MAP.UPDATE_INDICES")
+        .arg("test_ns")
+        .arg("k2")
+        .arg("SET")
+        .arg("first")
+        .arg("y")
+        .query(&mut conn)?;
+    assert_eq!(0, updated);
+    assert_members(&mut conn, vec!["k1"], "idx_{test_ns}:first:w")?;
+
+    Ok(())
 }